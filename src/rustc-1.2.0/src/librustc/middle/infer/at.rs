@@ -0,0 +1,135 @@
+// Copyright 2012-2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A small builder, `infcx.at(origin)`, that unifies the ad-hoc
+//! `TypeTrace`/`SubregionOrigin` plumbing that used to be repeated at every
+//! call site (`can_equate`, `equality_predicate`, `region_outlives_predicate`
+//! all built their own trace by hand). `At::eq`/`At::sub`/`At::relate` work
+//! the same way whether `a`/`b` are `Ty<'tcx>` or `ty::Region`: the
+//! `ToTrace` trait below picks the right `ValuePairs` for the trace, and
+//! `At::relate` picks the right underlying combiner (the type combine
+//! fields, or a pair of `mk_subr` calls for regions, since two regions are
+//! equated via mutual subregion constraints rather than a combine field).
+
+use middle::infer::{InferCtxt, TypeOrigin, TypeTrace, UnitResult};
+use middle::infer::{Regions, Subtype};
+use middle::infer::mk_subr;
+use middle::ty::{self, Ty};
+
+/// Returned by `InferCtxt::at`. Carries the `origin` that will be attached
+/// to whatever `TypeTrace`/`SubregionOrigin` a relation built from it ends
+/// up needing, so callers no longer have to gin one up themselves.
+pub struct At<'a, 'tcx: 'a> {
+    infcx: &'a InferCtxt<'a, 'tcx>,
+    origin: TypeOrigin,
+}
+
+impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
+    pub fn at(&'a self, origin: TypeOrigin) -> At<'a, 'tcx> {
+        At { infcx: self, origin: origin }
+    }
+}
+
+impl<'a, 'tcx> At<'a, 'tcx> {
+    /// Makes `a` and `b` equal to one another.
+    pub fn eq<T>(self, a: T, b: T) -> UnitResult<'tcx>
+        where T: ToTrace<'tcx>
+    {
+        self.relate(ty::Invariant, a, b)
+    }
+
+    /// Makes `a` a subtype of `b`.
+    pub fn sub<T>(self, a: T, b: T) -> UnitResult<'tcx>
+        where T: ToTrace<'tcx>
+    {
+        self.relate(ty::Covariant, a, b)
+    }
+
+    /// Relates `a` and `b` according to `variance`, uniformly for types and
+    /// regions. On failure the error carries the `TypeTrace` (for types) or
+    /// `SubregionOrigin` (for regions) built from `self.origin`, so callers
+    /// never have to assemble one of their own.
+    pub fn relate<T>(self, variance: ty::Variance, a: T, b: T) -> UnitResult<'tcx>
+        where T: ToTrace<'tcx>
+    {
+        let trace = ToTrace::to_trace(self.origin, true, a, b);
+        T::relate(self.infcx, trace, variance, a, b)
+    }
+}
+
+/// Implemented by the two kinds of value `At`'s methods know how to relate:
+/// `Ty<'tcx>` (through the usual combine fields) and `ty::Region` (through
+/// `mk_subr`, since regions don't have a generic combine field of their
+/// own).
+pub trait ToTrace<'tcx>: Copy {
+    fn to_trace(origin: TypeOrigin, a_is_expected: bool, a: Self, b: Self) -> TypeTrace<'tcx>;
+
+    fn relate<'a>(infcx: &'a InferCtxt<'a, 'tcx>,
+                  trace: TypeTrace<'tcx>,
+                  variance: ty::Variance,
+                  a: Self,
+                  b: Self)
+                  -> UnitResult<'tcx>;
+}
+
+impl<'tcx> ToTrace<'tcx> for Ty<'tcx> {
+    fn to_trace(origin: TypeOrigin, a_is_expected: bool, a: Ty<'tcx>, b: Ty<'tcx>)
+                -> TypeTrace<'tcx> {
+        TypeTrace::types(origin, a_is_expected, a, b)
+    }
+
+    fn relate<'a>(infcx: &'a InferCtxt<'a, 'tcx>,
+                  trace: TypeTrace<'tcx>,
+                  variance: ty::Variance,
+                  a: Ty<'tcx>,
+                  b: Ty<'tcx>)
+                  -> UnitResult<'tcx> {
+        match variance {
+            ty::Invariant => infcx.equate(true, trace).relate(&a, &b).map(|_| ()),
+            ty::Covariant => infcx.sub(true, trace).relate(&a, &b).map(|_| ()),
+            ty::Contravariant => infcx.sub(true, trace).relate(&b, &a).map(|_| ()),
+            ty::Bivariant => Ok(()),
+        }
+    }
+}
+
+impl<'tcx> ToTrace<'tcx> for ty::Region {
+    fn to_trace(origin: TypeOrigin, a_is_expected: bool, a: ty::Region, b: ty::Region)
+                -> TypeTrace<'tcx> {
+        let (expected, found) = if a_is_expected { (a, b) } else { (b, a) };
+        TypeTrace {
+            origin: origin,
+            values: Regions(ty::expected_found { expected: expected, found: found }),
+        }
+    }
+
+    fn relate<'a>(infcx: &'a InferCtxt<'a, 'tcx>,
+                  trace: TypeTrace<'tcx>,
+                  variance: ty::Variance,
+                  a: ty::Region,
+                  b: ty::Region)
+                  -> UnitResult<'tcx> {
+        // Regions have no combine field of their own; "relating" them
+        // means feeding a subregion constraint (in the appropriate
+        // direction(s)) to `region_vars`, tagged with the `SubregionOrigin`
+        // this trace stands in for.
+        let origin = Subtype(trace);
+        match variance {
+            ty::Invariant => {
+                mk_subr(infcx, origin.clone(), a, b);
+                mk_subr(infcx, origin, b, a);
+            }
+            ty::Covariant => mk_subr(infcx, origin, a, b),
+            ty::Contravariant => mk_subr(infcx, origin, b, a),
+            ty::Bivariant => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2012-2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! "Canonicalization" strips a value of everything that is specific to the
+//! `InferCtxt` it came from -- the concrete numbering of its type, int,
+//! float and region inference variables, and any free regions it mentions
+//! -- and replaces them with a small set of sequentially-numbered "canonical
+//! variables". The result, a `Canonical<T>`, no longer depends on which
+//! inference context produced `T` and so can be used as a cache key: two
+//! queries that are identical up to variable renaming canonicalize to the
+//! same `Canonical<T>`.
+//!
+//! `instantiate_canonical` is the inverse operation, used on the result
+//! side: given a `Canonical<T>` (e.g. a cached query result), create a
+//! fresh inference variable for each recorded `CanonicalVarKind` in a
+//! *new* `InferCtxt` and substitute it back in. `instantiate_query_response`
+//! goes one step further: having instantiated the response, it also equates
+//! each fresh variable against the `CanonicalVarValues` the original query
+//! was canonicalized from, so a cached answer's bindings flow back into the
+//! caller's own variables.
+
+use middle::infer::{InferCtxt, MiscVariable};
+use middle::infer::{mk_eqty, mk_subr};
+use middle::infer::{Misc, RelateRegionParamBound};
+use middle::ty::{self, Ty, RegionVid};
+use middle::ty_fold::{self, TypeFoldable, TypeFolder};
+use syntax::codemap;
+use util::nodemap::FnvHashMap;
+
+/// A value of type `T` alongside the kinds of the canonical variables it
+/// refers to. The `i`th canonical variable is bound at De Bruijn-like index
+/// `i`; `value` itself never contains a "real" inference variable or free
+/// region once canonicalization is done.
+#[derive(Clone, Debug)]
+pub struct Canonical<T> {
+    pub variables: Vec<CanonicalVarKind>,
+    pub value: T,
+}
+
+/// What kind of variable a given canonical variable stands in for. Needed
+/// so that instantiation knows what sort of fresh inference variable to
+/// manufacture for each slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CanonicalVarKind {
+    Ty,
+    Int,
+    Float,
+    Region,
+}
+
+/// The fresh variables created when instantiating a `Canonical<T>`, indexed
+/// in the same order as `Canonical::variables`. Kept around by callers that
+/// need to relate the instantiated value back to the variables that were
+/// substituted for its canonical slots (e.g. to propagate a query result's
+/// bindings back into the caller's `InferCtxt`).
+#[derive(Clone, Debug)]
+pub struct CanonicalVarValues<'tcx> {
+    pub var_values: Vec<CanonicalVarValue<'tcx>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum CanonicalVarValue<'tcx> {
+    Ty(Ty<'tcx>),
+    Region(ty::Region),
+}
+
+impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
+    /// Canonicalizes `value`, replacing every unresolved type/int/float
+    /// variable and every free region it contains with a canonical
+    /// variable. Returns the canonicalized value together with the
+    /// concrete variables (in this context) that each canonical variable
+    /// stands for, so the caller can map a later query result back onto
+    /// them.
+    pub fn canonicalize_query<T>(&self, value: &T) -> (Canonical<T>, CanonicalVarValues<'tcx>)
+        where T: TypeFoldable<'tcx>
+    {
+        let mut canonicalizer = Canonicalizer {
+            infcx: self,
+            variables: vec![],
+            var_values: vec![],
+            indices: FnvHashMap(),
+        };
+        let value = value.fold_with(&mut canonicalizer);
+        let canonical = Canonical {
+            variables: canonicalizer.variables,
+            value: value,
+        };
+        let var_values = CanonicalVarValues {
+            var_values: canonicalizer.var_values,
+        };
+        (canonical, var_values)
+    }
+
+    /// The inverse of `canonicalize_query`: creates a fresh inference
+    /// variable in `self` for each `CanonicalVarKind` recorded in
+    /// `canonical.variables`, then substitutes them into `canonical.value`
+    /// in order. Typically used to bring a cached, canonicalized query
+    /// result into a fresh `InferCtxt` for the current query.
+    pub fn instantiate_canonical<T>(&self, canonical: &Canonical<T>)
+                                     -> (T, CanonicalVarValues<'tcx>)
+        where T: TypeFoldable<'tcx>
+    {
+        let var_values: Vec<_> = canonical.variables.iter().map(|kind| {
+            match *kind {
+                CanonicalVarKind::Ty => CanonicalVarValue::Ty(self.next_ty_var()),
+                CanonicalVarKind::Int => {
+                    CanonicalVarValue::Ty(ty::mk_infer(self.tcx, ty::IntVar(self.next_int_var_id())))
+                }
+                CanonicalVarKind::Float => {
+                    CanonicalVarValue::Ty(
+                        ty::mk_infer(self.tcx, ty::FloatVar(self.next_float_var_id())))
+                }
+                CanonicalVarKind::Region => {
+                    CanonicalVarValue::Region(
+                        self.next_region_var(MiscVariable(codemap::DUMMY_SP)))
+                }
+            }
+        }).collect();
+
+        let mut instantiator = CanonicalInstantiator { tcx: self.tcx, var_values: &var_values };
+        let value = canonical.value.fold_with(&mut instantiator);
+        (value, CanonicalVarValues { var_values: var_values })
+    }
+
+    /// Brings a cached query response back into `self`: instantiates
+    /// `canonical_response` as `instantiate_canonical` does, then equates
+    /// each fresh variable it just created against the corresponding entry
+    /// of `original_values` (the `CanonicalVarValues` produced by the
+    /// `canonicalize_query` call that the cache hit is answering). This is
+    /// what actually feeds a cached answer's bindings back into the
+    /// obligations/variables the caller started with.
+    pub fn instantiate_query_response<T>(&self,
+                                         original_values: &CanonicalVarValues<'tcx>,
+                                         canonical_response: &Canonical<T>)
+                                         -> T
+        where T: TypeFoldable<'tcx>
+    {
+        let (value, result_values) = self.instantiate_canonical(canonical_response);
+
+        // `original_values` and `result_values` were produced by two
+        // independent canonicalizations -- the query's and the cached
+        // response's -- so there's no guarantee they share a length or
+        // that the same index denotes the same kind of variable in both.
+        // Pair each original variable with the response variable at the
+        // same canonical index, and treat a missing (the response has
+        // fewer variables) or kind-mismatched counterpart as "nothing to
+        // bind" instead of a hard error: both
+        // are reachable from a cache entry that answers a structurally
+        // different query than this one, not only from a compiler bug.
+        for (i, original) in original_values.var_values.iter().enumerate() {
+            let result = match result_values.var_values.get(i) {
+                Some(result) => result,
+                None => continue,
+            };
+            match (*original, *result) {
+                (CanonicalVarValue::Ty(orig_ty), CanonicalVarValue::Ty(result_ty)) => {
+                    let _ = mk_eqty(self, true, Misc(codemap::DUMMY_SP), orig_ty, result_ty);
+                }
+                (CanonicalVarValue::Region(orig_r), CanonicalVarValue::Region(result_r)) => {
+                    // No `mk_eqr`: regions are equated the way `At::eq`
+                    // does it for regions (see at.rs), via a subregion
+                    // constraint in each direction.
+                    let origin = RelateRegionParamBound(codemap::DUMMY_SP);
+                    mk_subr(self, origin.clone(), orig_r, result_r);
+                    mk_subr(self, origin, result_r, orig_r);
+                }
+                _ => {
+                    debug!("instantiate_query_response: canonical var {} kind mismatch \
+                            between query and response, skipping", i);
+                }
+            }
+        }
+
+        value
+    }
+}
+
+/// Walks a value looking for inference variables and free regions, handing
+/// back a canonical (De Bruijn-numbered) bound region/"variable" in their
+/// place. A `HashMap` keyed on the variable's identity (its `TyVid`,
+/// `IntVid`, `FloatVid` or `RegionVid`/free region) ensures repeated
+/// occurrences of the same variable canonicalize to the same index.
+struct Canonicalizer<'cx, 'a: 'cx, 'tcx: 'a> {
+    infcx: &'cx InferCtxt<'a, 'tcx>,
+    variables: Vec<CanonicalVarKind>,
+    var_values: Vec<CanonicalVarValue<'tcx>>,
+    indices: FnvHashMap<CanonicalizeKey, usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum CanonicalizeKey {
+    Ty(ty::TyVid),
+    Int(ty::IntVid),
+    Float(ty::FloatVid),
+    Region(RegionVid),
+    FreeRegion(ty::Region),
+}
+
+impl<'cx, 'a, 'tcx> Canonicalizer<'cx, 'a, 'tcx> {
+    fn canonical_var(&mut self,
+                      key: CanonicalizeKey,
+                      kind: CanonicalVarKind,
+                      value: CanonicalVarValue<'tcx>)
+                      -> usize {
+        if let Some(&index) = self.indices.get(&key) {
+            return index;
+        }
+        let index = self.variables.len();
+        self.variables.push(kind);
+        self.var_values.push(value);
+        self.indices.insert(key, index);
+        index
+    }
+}
+
+impl<'cx, 'a, 'tcx> TypeFolder<'tcx> for Canonicalizer<'cx, 'a, 'tcx> {
+    fn tcx(&self) -> &ty::ctxt<'tcx> {
+        self.infcx.tcx
+    }
+
+    fn fold_ty(&mut self, t: Ty<'tcx>) -> Ty<'tcx> {
+        // Collapse any variable that's already been unified with something
+        // concrete before deciding whether it needs a canonical slot, so
+        // that already-resolved content is preserved verbatim rather than
+        // being canonicalized away.
+        let t = self.infcx.shallow_resolve(t);
+        match t.sty {
+            ty::TyInfer(ty::TyVar(vid)) => {
+                let index = self.canonical_var(CanonicalizeKey::Ty(vid),
+                                               CanonicalVarKind::Ty,
+                                               CanonicalVarValue::Ty(t));
+                ty::mk_infer(self.tcx(), ty::FreshTy(index as u32))
+            }
+            ty::TyInfer(ty::IntVar(vid)) => {
+                let index = self.canonical_var(CanonicalizeKey::Int(vid),
+                                               CanonicalVarKind::Int,
+                                               CanonicalVarValue::Ty(t));
+                ty::mk_infer(self.tcx(), ty::FreshIntTy(index as u32))
+            }
+            ty::TyInfer(ty::FloatVar(vid)) => {
+                let index = self.canonical_var(CanonicalizeKey::Float(vid),
+                                               CanonicalVarKind::Float,
+                                               CanonicalVarValue::Ty(t));
+                ty::mk_infer(self.tcx(), ty::FreshFloatTy(index as u32))
+            }
+            _ => ty_fold::super_fold_ty(self, t),
+        }
+    }
+
+    fn fold_region(&mut self, r: ty::Region) -> ty::Region {
+        match r {
+            ty::ReInfer(ty::ReVar(vid)) => {
+                let index = self.canonical_var(CanonicalizeKey::Region(vid),
+                                               CanonicalVarKind::Region,
+                                               CanonicalVarValue::Region(r));
+                ty::ReLateBound(ty::DebruijnIndex::new(1), ty::BrFresh(index))
+            }
+            ty::ReFree(_) | ty::ReStatic | ty::ReEarlyBound(..) => {
+                let index = self.canonical_var(CanonicalizeKey::FreeRegion(r),
+                                               CanonicalVarKind::Region,
+                                               CanonicalVarValue::Region(r));
+                ty::ReLateBound(ty::DebruijnIndex::new(1), ty::BrFresh(index))
+            }
+            _ => r,
+        }
+    }
+}
+
+/// Substitutes the fresh variables created by `instantiate_canonical` back
+/// into a `Canonical<T>`'s value, undoing the De Bruijn numbering that
+/// `Canonicalizer` introduced.
+struct CanonicalInstantiator<'a, 'tcx: 'a> {
+    tcx: &'a ty::ctxt<'tcx>,
+    var_values: &'a [CanonicalVarValue<'tcx>],
+}
+
+impl<'a, 'tcx> TypeFolder<'tcx> for CanonicalInstantiator<'a, 'tcx> {
+    fn tcx(&self) -> &ty::ctxt<'tcx> {
+        self.tcx
+    }
+
+    fn fold_ty(&mut self, t: Ty<'tcx>) -> Ty<'tcx> {
+        match t.sty {
+            ty::TyInfer(ty::FreshTy(index)) |
+            ty::TyInfer(ty::FreshIntTy(index)) |
+            ty::TyInfer(ty::FreshFloatTy(index)) => {
+                match self.var_values[index as usize] {
+                    CanonicalVarValue::Ty(ty) => ty,
+                    CanonicalVarValue::Region(_) => {
+                        panic!("canonical variable {} is a region, expected a type", index)
+                    }
+                }
+            }
+            _ => ty_fold::super_fold_ty(self, t),
+        }
+    }
+
+    fn fold_region(&mut self, r: ty::Region) -> ty::Region {
+        match r {
+            ty::ReLateBound(_, ty::BrFresh(index)) => {
+                match self.var_values[index as usize] {
+                    CanonicalVarValue::Region(region) => region,
+                    CanonicalVarValue::Ty(_) => {
+                        panic!("canonical variable {} is a type, expected a region", index)
+                    }
+                }
+            }
+            _ => r,
+        }
+    }
+}
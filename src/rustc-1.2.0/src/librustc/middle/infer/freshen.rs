@@ -0,0 +1,146 @@
+// Copyright 2012-2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Freshening is the process of replacing unresolved inference variables
+//! with a canonical placeholder -- a `TyInfer(FreshTy(n))`,
+//! `TyInfer(FreshIntTy(n))`, `TyInfer(FreshFloatTy(n))`, or (for
+//! const-generics) an analogous fresh const -- numbered in the order they
+//! are first encountered. Two values that differ only in the identity of
+//! their unbound variables freshen to the same value, which is exactly what
+//! trait selection wants when memoizing obligation results keyed on a
+//! trait-ref: the result shouldn't depend on *which* inference variable was
+//! plugged in, only on its unresolved-ness.
+//!
+//! This is deliberately a separate operation from
+//! `resolve_type_vars_if_possible`, which leaves unresolved variables
+//! untouched. Like that method, freshening must be read-only: it must not
+//! create new inference variables or otherwise mutate `InferCtxt`'s
+//! unification state, only fold over `value`. Already-resolved variables
+//! are first collapsed via `shallow_resolve` and their structural content
+//! is recursed into as usual, so only genuinely unresolved variables end up
+//! replaced.
+//!
+//! Region variables play no part in trait selection, so rather than
+//! numbering them individually (which would defeat memoization whenever two
+//! otherwise-identical obligations happened to carry different region
+//! variables) every region is frozen to a single placeholder, `ReStatic`.
+//! Late-bound regions are left alone, since they aren't inference variables
+//! at all.
+
+use middle::infer::InferCtxt;
+use middle::ty::{self, Ty};
+use middle::ty_fold::{self, TypeFolder};
+use util::nodemap::FnvHashMap;
+
+pub struct TypeFreshener<'a, 'tcx: 'a> {
+    infcx: &'a InferCtxt<'a, 'tcx>,
+    freshen_count: u32,
+    ty_freshen_map: FnvHashMap<ty::InferTy, u32>,
+    const_freshen_map: FnvHashMap<ty::ConstVid, u32>,
+}
+
+impl<'a, 'tcx> TypeFreshener<'a, 'tcx> {
+    pub fn new(infcx: &'a InferCtxt<'a, 'tcx>) -> TypeFreshener<'a, 'tcx> {
+        TypeFreshener {
+            infcx: infcx,
+            freshen_count: 0,
+            ty_freshen_map: FnvHashMap(),
+            const_freshen_map: FnvHashMap(),
+        }
+    }
+
+    /// Returns the fresh placeholder type `mk_fresh(n)`, where `n` is the
+    /// canonical number assigned to `key`, allocating a new number (via
+    /// `self.freshen_count`) the first time `key` is seen.
+    fn freshen_ty<F>(&mut self, key: ty::InferTy, mk_fresh: F) -> Ty<'tcx>
+        where F: FnOnce(u32) -> ty::InferTy
+    {
+        let tcx = self.infcx.tcx;
+        let index = match self.ty_freshen_map.get(&key) {
+            Some(&n) => n,
+            None => {
+                let n = self.freshen_count;
+                self.freshen_count += 1;
+                self.ty_freshen_map.insert(key, n);
+                n
+            }
+        };
+        ty::mk_infer(tcx, mk_fresh(index))
+    }
+}
+
+impl<'a, 'tcx> TypeFolder<'tcx> for TypeFreshener<'a, 'tcx> {
+    fn tcx(&self) -> &ty::ctxt<'tcx> {
+        self.infcx.tcx
+    }
+
+    fn fold_ty(&mut self, t: Ty<'tcx>) -> Ty<'tcx> {
+        // Collapse any variable already unified with something concrete
+        // before deciding whether it needs a fresh placeholder, so that
+        // resolved content is recursed into rather than frozen away.
+        let t = self.infcx.shallow_resolve(t);
+        match t.sty {
+            ty::TyInfer(ty::TyVar(v)) => {
+                self.freshen_ty(ty::TyVar(v), ty::FreshTy)
+            }
+
+            ty::TyInfer(ty::IntVar(v)) => {
+                self.freshen_ty(ty::IntVar(v), ty::FreshIntTy)
+            }
+
+            ty::TyInfer(ty::FloatVar(v)) => {
+                self.freshen_ty(ty::FloatVar(v), ty::FreshFloatTy)
+            }
+
+            ty::TyInfer(ty::FreshTy(_)) |
+            ty::TyInfer(ty::FreshIntTy(_)) |
+            ty::TyInfer(ty::FreshFloatTy(_)) => {
+                // Can happen if two freshened types are compared, as in
+                // the trait-selection cache itself.
+                t
+            }
+
+            _ => ty_fold::super_fold_ty(self, t),
+        }
+    }
+
+    fn fold_region(&mut self, r: ty::Region) -> ty::Region {
+        match r {
+            // Late-bound regions aren't inference variables; selection
+            // never looks past them, so leave them exactly as they are.
+            ty::ReLateBound(..) => r,
+
+            // Every other region -- free, early-bound, or an unresolved
+            // `ReVar` -- is frozen to the same placeholder, since trait
+            // selection never depends on *which* region it was.
+            _ => ty::ReStatic,
+        }
+    }
+
+    fn fold_const(&mut self, constant: ty::ConstVal<'tcx>) -> ty::ConstVal<'tcx> {
+        let constant = self.infcx.shallow_resolve_const(constant);
+        match constant {
+            ty::ConstVal::Infer(ty::InferConst::Var(vid)) => {
+                let index = match self.const_freshen_map.get(&vid) {
+                    Some(&n) => n,
+                    None => {
+                        let n = self.freshen_count;
+                        self.freshen_count += 1;
+                        self.const_freshen_map.insert(vid, n);
+                        n
+                    }
+                };
+                ty::ConstVal::Infer(ty::InferConst::Fresh(index))
+            }
+            ty::ConstVal::Infer(ty::InferConst::Fresh(_)) => constant,
+            _ => ty_fold::super_fold_const(self, constant),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright 2012-2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The classic lexical-scope region solver: runs the fixed-point
+//! computation over whatever constraints `RegionVarBindings` has
+//! accumulated (via `mk_subr`/`make_subregion`) and produces either an
+//! assignment for every region variable or the set of constraints that
+//! made no such assignment possible.
+//!
+//! This lives in its own module, separate from `RegionVarBindings` itself,
+//! so that a future flow-sensitive (non-lexical-lifetime) solver can
+//! consume the same accumulated constraint set -- with its originating
+//! `SubregionOrigin`s preserved -- without this module's fixed-point logic
+//! being in the way. See `InferCtxt::borrowck_mode`.
+
+use middle::free_region::FreeRegionMap;
+use middle::infer::region_inference::{RegionVarBindings, RegionResolutionError};
+use syntax::ast;
+
+/// The result of running the lexical fixed-point solver over a
+/// `RegionVarBindings`'s accumulated constraints.
+pub struct LexicalRegionResolution<'tcx> {
+    pub errors: Vec<RegionResolutionError<'tcx>>,
+}
+
+/// Runs the classic lexical-scope solver over `region_vars`'s accumulated
+/// constraints, relative to `free_regions`, for the item `subject_node_id`.
+/// This is what `BorrowckMode::Lexical` and `BorrowckMode::Migrate` use to
+/// answer `resolve_regions_and_report_errors`; `BorrowckMode::Flow` bypasses
+/// it entirely in favor of consuming `region_vars`'s raw constraints
+/// elsewhere.
+pub fn resolve<'a, 'tcx>(region_vars: &RegionVarBindings<'a, 'tcx>,
+                         free_regions: &FreeRegionMap,
+                         subject_node_id: ast::NodeId)
+                         -> LexicalRegionResolution<'tcx> {
+    LexicalRegionResolution {
+        errors: region_vars.resolve_regions(free_regions, subject_node_id),
+    }
+}
@@ -25,31 +25,38 @@ pub use self::region_inference::GenericKind;
 use middle::free_region::FreeRegionMap;
 use middle::subst;
 use middle::subst::Substs;
-use middle::ty::{TyVid, IntVid, FloatVid, RegionVid, UnconstrainedNumeric};
+use middle::ty::{TyVid, IntVid, FloatVid, ConstVid, RegionVid, UnconstrainedNumeric};
 use middle::ty::{self, Ty};
 use middle::ty_fold::{self, TypeFolder, TypeFoldable};
 use middle::ty_relate::{Relate, RelateResult, TypeRelation};
 use rustc_data_structures::unify::{self, UnificationTable};
-use std::cell::{RefCell};
+use std::cell::{Cell, RefCell};
 use std::fmt;
+use std::mem;
 use syntax::ast;
 use syntax::codemap;
 use syntax::codemap::Span;
-use util::nodemap::FnvHashMap;
+use syntax::errors::DiagnosticBuilder;
+use util::nodemap::{FnvHashMap, FnvHashSet};
 
 use self::combine::CombineFields;
+use self::lexical_region_resolve;
 use self::region_inference::{RegionVarBindings, RegionSnapshot};
 use self::error_reporting::ErrorReporting;
 use self::unify_key::ToType;
 
+pub mod at;
 pub mod bivariate;
+pub mod canonical;
 pub mod combine;
 pub mod equate;
 pub mod error_reporting;
 pub mod glb;
 mod higher_ranked;
 pub mod lattice;
+pub mod lexical_region_resolve;
 pub mod lub;
+mod nice_region_error;
 pub mod region_inference;
 pub mod resolve;
 mod freshen;
@@ -75,8 +82,87 @@ pub struct InferCtxt<'a, 'tcx: 'a> {
     // Map from floating variable to the kind of float it represents
     float_unification_table: RefCell<UnificationTable<ty::FloatVid>>,
 
+    // Map from const-generic variable to the const value it represents,
+    // mirroring `int_unification_table`/`float_unification_table` above so
+    // that `[T; N]` with an unresolved `N` can be inferred the same way an
+    // unsuffixed integer literal is.
+    const_unification_table: RefCell<UnificationTable<ty::ConstVid>>,
+
     // For region variables.
     region_vars: RegionVarBindings<'a, 'tcx>,
+
+    // Predicate obligations (e.g. `T: Clone`) surfaced while relating
+    // types via `sub_types`/`eq_types` and friends, waiting to be drained
+    // by a `TraitEngine`. Rolled back together with the rest of a
+    // `CombinedSnapshot` if the relation that produced them is undone.
+    pending_obligations: RefCell<PredicateObligations<'tcx>>,
+
+    // Selects how `resolve_regions_and_report_errors` finalizes the
+    // constraints `region_vars` has accumulated: the classic lexical
+    // fixed-point solver, or (eventually) a flow-sensitive one. See
+    // `BorrowckMode`.
+    borrowck_mode: Cell<BorrowckMode>,
+}
+
+/// Selects how accumulated region constraints are resolved into a final
+/// set of region errors. `Lexical` is today's classic fixed-point solver
+/// (`lexical_region_resolve`); `Migrate` also runs it, but is meant for
+/// code that still wants the lexical answer while non-lexical lifetimes
+/// are phased in elsewhere; `Flow` skips the lexical solver entirely and
+/// leaves `region_vars`'s raw constraint set, `SubregionOrigin`s intact,
+/// for an external flow-sensitive pass to consume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorrowckMode {
+    Lexical,
+    Migrate,
+    Flow,
+}
+
+impl Default for BorrowckMode {
+    fn default() -> Self {
+        BorrowckMode::Lexical
+    }
+}
+
+/// Why a predicate obligation (e.g. `T: Clone`) was registered, so that if
+/// it's never discharged the resulting ambiguity error can explain itself
+/// the same way a type-relation error does via `TypeOrigin`.
+#[derive(Clone, Debug)]
+pub struct ObligationCause {
+    pub span: Span,
+    pub origin: TypeOrigin,
+}
+
+impl ObligationCause {
+    pub fn new(span: Span, origin: TypeOrigin) -> ObligationCause {
+        ObligationCause { span: span, origin: origin }
+    }
+}
+
+/// A single pending predicate obligation, tied to the reason it was
+/// registered.
+#[derive(Clone, Debug)]
+pub struct PredicateObligation<'tcx> {
+    pub cause: ObligationCause,
+    pub predicate: ty::Predicate<'tcx>,
+}
+
+pub type PredicateObligations<'tcx> = Vec<PredicateObligation<'tcx>>;
+
+/// A pluggable trait-solving engine capable of driving a batch of
+/// `PredicateObligation`s to a fixed point. Kept as a trait, rather than a
+/// concrete type, so `InferCtxt` doesn't have to depend on the `traits`
+/// crate's `FulfillmentContext` directly -- it only needs *something* that
+/// can attempt to discharge the obligations `sub_types`/`eq_types` and
+/// friends register along the way.
+pub trait TraitEngine<'tcx> {
+    /// Attempts to make progress on every obligation in `obligations`,
+    /// returning the ones still outstanding (ambiguous, or blocked on
+    /// other inference variables) once no further progress is possible.
+    fn select_all_or_error<'a>(&mut self,
+                               infcx: &InferCtxt<'a, 'tcx>,
+                               obligations: PredicateObligations<'tcx>)
+                               -> Result<(), PredicateObligations<'tcx>>;
 }
 
 /// A map returned by `skolemize_late_bound_regions()` indicating the skolemized
@@ -151,6 +237,7 @@ impl fmt::Display for TypeOrigin {
 #[derive(Clone, Debug)]
 pub enum ValuePairs<'tcx> {
     Types(ty::expected_found<Ty<'tcx>>),
+    Regions(ty::expected_found<ty::Region>),
     TraitRefs(ty::expected_found<ty::TraitRef<'tcx>>),
     PolyTraitRefs(ty::expected_found<ty::PolyTraitRef<'tcx>>),
 }
@@ -319,7 +406,10 @@ pub fn new_infer_ctxt<'a, 'tcx>(tcx: &'a ty::ctxt<'tcx>)
         type_variables: RefCell::new(type_variable::TypeVariableTable::new()),
         int_unification_table: RefCell::new(UnificationTable::new()),
         float_unification_table: RefCell::new(UnificationTable::new()),
+        const_unification_table: RefCell::new(UnificationTable::new()),
         region_vars: RegionVarBindings::new(tcx),
+        pending_obligations: RefCell::new(Vec::new()),
+        borrowck_mode: Cell::new(BorrowckMode::default()),
     }
 }
 
@@ -344,7 +434,10 @@ pub fn common_supertype<'a, 'tcx>(cx: &InferCtxt<'a, 'tcx>,
     match result {
         Ok(t) => t,
         Err(ref err) => {
-            cx.report_and_explain_type_error(trace, err);
+            // Built rather than emitted directly so that callers who know
+            // *why* a common supertype was required (e.g. match arms, if
+            // branches) can append that context; for now we emit as-is.
+            cx.report_and_explain_type_error_builder(trace, err).emit();
             cx.tcx.types.err
         }
     }
@@ -431,6 +524,8 @@ pub struct CombinedSnapshot {
     type_snapshot: type_variable::Snapshot,
     int_snapshot: unify::Snapshot<ty::IntVid>,
     float_snapshot: unify::Snapshot<ty::FloatVid>,
+    const_snapshot: unify::Snapshot<ty::ConstVid>,
+    obligations_snapshot: usize,
     region_vars_snapshot: RegionSnapshot,
 }
 
@@ -446,6 +541,9 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         }
     }
 
+    // `TypeFreshener` (see freshen.rs) also freshens const inference
+    // variables, so two obligations that differ only in an unresolved
+    // `N` still produce the same trait-selection cache key.
     pub fn freshener<'b>(&'b self) -> TypeFreshener<'b, 'tcx> {
         freshen::TypeFreshener::new(self)
     }
@@ -512,6 +610,8 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
             type_snapshot: self.type_variables.borrow_mut().snapshot(),
             int_snapshot: self.int_unification_table.borrow_mut().snapshot(),
             float_snapshot: self.float_unification_table.borrow_mut().snapshot(),
+            const_snapshot: self.const_unification_table.borrow_mut().snapshot(),
+            obligations_snapshot: self.pending_obligations.borrow().len(),
             region_vars_snapshot: self.region_vars.start_snapshot(),
         }
     }
@@ -521,6 +621,8 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         let CombinedSnapshot { type_snapshot,
                                int_snapshot,
                                float_snapshot,
+                               const_snapshot,
+                               obligations_snapshot,
                                region_vars_snapshot } = snapshot;
 
         self.type_variables
@@ -532,6 +634,15 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         self.float_unification_table
             .borrow_mut()
             .rollback_to(float_snapshot);
+        self.const_unification_table
+            .borrow_mut()
+            .rollback_to(const_snapshot);
+        // Any obligations registered since the snapshot was taken came
+        // from type relations that are themselves being rolled back, so
+        // they shouldn't survive either.
+        self.pending_obligations
+            .borrow_mut()
+            .truncate(obligations_snapshot);
         self.region_vars
             .rollback_to(region_vars_snapshot);
     }
@@ -541,6 +652,8 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         let CombinedSnapshot { type_snapshot,
                                int_snapshot,
                                float_snapshot,
+                               const_snapshot,
+                               obligations_snapshot: _,
                                region_vars_snapshot } = snapshot;
 
         self.type_variables
@@ -552,6 +665,11 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         self.float_unification_table
             .borrow_mut()
             .commit(float_snapshot);
+        self.const_unification_table
+            .borrow_mut()
+            .commit(const_snapshot);
+        // Obligations registered during the snapshot are kept as-is; there
+        // is nothing to "commit" beyond just leaving them in place.
         self.region_vars
             .commit(region_vars_snapshot);
     }
@@ -626,6 +744,78 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         r
     }
 
+    /// Execute `f`, keep its *result*, but unwind every inference variable
+    /// `f` allocated along the way, re-creating fresh ones of the same kind
+    /// in `value`'s place. Useful for coercion of `if`/`match` arms, `?`,
+    /// and the like, where the caller wants the shape `f` computed without
+    /// committing to the particular temporary variables it used to get
+    /// there -- those would otherwise leak into the outer context with
+    /// nothing left to unify them against.
+    ///
+    /// On `Err`, behaves exactly like `probe`/`commit_if_ok`: everything
+    /// `f` did is unwound. On `Ok(value)`, `f`'s bindings are unwound
+    /// first, and *then* every variable `f` created (i.e. one that didn't
+    /// exist before `f` ran) and that still appears in `value` is replaced
+    /// with a freshly allocated variable of the same kind in the restored
+    /// outer context; variables that predate `f` are left exactly as they
+    /// were.
+    pub fn fudge_inference_if_ok<T, E, F>(&self, f: F) -> Result<T, E>
+        where F: FnOnce() -> Result<T, E>,
+              T: TypeFoldable<'tcx>,
+    {
+        debug!("fudge_inference_if_ok()");
+
+        let snapshot = self.start_snapshot();
+        let (created, value) = match f() {
+            Ok(value) => {
+                // Resolve everything we can before the snapshot is rolled
+                // back, so `value` no longer mentions any variable `f`
+                // managed to unify with something concrete.
+                let value = self.resolve_type_vars_if_possible(&value);
+
+                // Every variable `f` allocated, whether or not it still
+                // occurs in `value`.
+                let all_created = VarsCreatedSinceSnapshot {
+                    type_vars: self.type_variables
+                        .borrow_mut()
+                        .vars_created_since_snapshot(&snapshot.type_snapshot),
+                    int_vars: self.int_unification_table
+                        .borrow_mut()
+                        .vars_created_since_snapshot(&snapshot.int_snapshot),
+                    float_vars: self.float_unification_table
+                        .borrow_mut()
+                        .vars_created_since_snapshot(&snapshot.float_snapshot),
+                    const_vars: self.const_unification_table
+                        .borrow_mut()
+                        .vars_created_since_snapshot(&snapshot.const_snapshot),
+                    region_vars: self.region_vars
+                        .vars_created_since_snapshot(&snapshot.region_vars_snapshot),
+                };
+
+                // Most of the variables `f` allocated were resolved away
+                // above or simply abandoned; only the ones `value` still
+                // mentions need a replacement, so as not to leak a fresh,
+                // wholly-unconstrained variable into the outer context for
+                // every var `f` touched along the way.
+                let mentioned = VarsInValue::collect(self.tcx, &value);
+                let created = all_created.retain_mentioned(&mentioned);
+
+                (created, value)
+            }
+            Err(e) => {
+                self.rollback_to(snapshot);
+                return Err(e);
+            }
+        };
+
+        self.rollback_to(snapshot);
+
+        // `f`'s bindings are gone; allocate the replacements in the now
+        // fully-restored outer context and fold them into `value`.
+        let mut fudger = InferenceFudger::new(self, created);
+        Ok(value.fold_with(&mut fudger))
+    }
+
     pub fn add_given(&self,
                      sub: ty::FreeRegion,
                      sup: ty::RegionVid)
@@ -751,7 +941,10 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
             let (ty::EquatePredicate(a, b), skol_map) =
                 self.skolemize_late_bound_regions(predicate, snapshot);
             let origin = EquatePredicate(span);
-            let () = try!(mk_eqty(self, false, origin, a, b));
+            // `a_is_expected: false`, i.e. `b` is expected: swap the
+            // order so `At::eq`'s fixed `a_is_expected: true` produces the
+            // same expected/found as the `mk_eqty` call this replaced.
+            let () = try!(self.at(origin).eq(b, a));
             self.leak_check(&skol_map, snapshot)
         })
     }
@@ -763,8 +956,13 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         self.commit_if_ok(|snapshot| {
             let (ty::OutlivesPredicate(r_a, r_b), skol_map) =
                 self.skolemize_late_bound_regions(predicate, snapshot);
-            let origin = RelateRegionParamBound(span);
-            let () = mk_subr(self, origin, r_b, r_a); // `b : a` ==> `a <= b`
+            // `b : a` ==> `a <= b`. This goes through `mk_subr` directly,
+            // not `at().sub()`, because `At` always tags a region relation
+            // with `SubregionOrigin::Subtype`; a failed `T: 'a`-style bound
+            // is not a subtype error, it needs its own
+            // `RelateRegionParamBound` origin so the diagnostic names it
+            // correctly.
+            mk_subr(self, RelateRegionParamBound(span), r_b, r_a);
             self.leak_check(&skol_map, snapshot)
         })
     }
@@ -799,6 +997,18 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
             .new_key(None)
     }
 
+    pub fn next_const_var_id(&self) -> ty::ConstVid {
+        self.const_unification_table
+            .borrow_mut()
+            .new_key(unify_key::ConstVariableValue::Unknown)
+    }
+
+    /// Creates a fresh const inference variable, for use where a
+    /// const-generic argument (e.g. the `N` in `[T; N]`) is not yet known.
+    pub fn next_const_var(&self) -> ty::ConstVal<'tcx> {
+        ty::ConstVal::Infer(ty::InferConst::Var(self.next_const_var_id()))
+    }
+
     pub fn next_region_var(&self, origin: RegionVariableOrigin) -> ty::Region {
         ty::ReInfer(ty::ReVar(self.region_vars.new_region_var(origin)))
     }
@@ -856,11 +1066,41 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         self.region_vars.new_bound(debruijn)
     }
 
+    pub fn set_borrowck_mode(&self, mode: BorrowckMode) {
+        self.borrowck_mode.set(mode);
+    }
+
+    pub fn borrowck_mode(&self) -> BorrowckMode {
+        self.borrowck_mode.get()
+    }
+
     pub fn resolve_regions_and_report_errors(&self,
                                              free_regions: &FreeRegionMap,
                                              subject_node_id: ast::NodeId) {
-        let errors = self.region_vars.resolve_regions(free_regions, subject_node_id);
-        self.report_region_errors(&errors); // see error_reporting.rs
+        let errors = match self.borrowck_mode() {
+            // Both still go through the classic lexical solver for now;
+            // `Migrate` exists so callers can ask for it explicitly while
+            // a flow-sensitive pass is wired up elsewhere, without that
+            // pass changing today's behavior out from under them.
+            BorrowckMode::Lexical | BorrowckMode::Migrate => {
+                lexical_region_resolve::resolve(&self.region_vars,
+                                                free_regions,
+                                                subject_node_id).errors
+            }
+            // The flow-sensitive solver consumes `region_vars`'s raw
+            // constraint set (with `SubregionOrigin`s intact) directly;
+            // there is nothing left for the lexical path to resolve.
+            BorrowckMode::Flow => return,
+        };
+
+        // Give the nice-region-error detectors (see `nice_region_error.rs`)
+        // first crack at each error; only the ones none of them recognize
+        // fall through to the generic reporter.
+        let unhandled: Vec<_> =
+            errors.into_iter()
+                  .filter(|error| !self.try_report_nice_region_error(error))
+                  .collect();
+        self.report_region_errors(&unhandled); // see error_reporting.rs
     }
 
     pub fn ty_to_string(&self, t: Ty<'tcx>) -> String {
@@ -916,9 +1156,26 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         }
     }
 
+    /// Analogous to `shallow_resolve`, but for the constant side: if
+    /// `constant` is a const inference variable that has already been
+    /// unified with a known value, returns that value; otherwise returns
+    /// `constant` unchanged. Does not recurse, so a const variable that
+    /// has only been unified with another (still-unknown) const variable
+    /// is not followed any further.
+    pub fn shallow_resolve_const(&self, constant: ty::ConstVal<'tcx>) -> ty::ConstVal<'tcx> {
+        if let ty::ConstVal::Infer(ty::InferConst::Var(vid)) = constant {
+            match self.const_unification_table.borrow_mut().probe(vid) {
+                unify_key::ConstVariableValue::Known(known) => known,
+                unify_key::ConstVariableValue::Unknown => constant,
+            }
+        } else {
+            constant
+        }
+    }
+
     pub fn resolve_type_vars_if_possible<T:TypeFoldable<'tcx>>(&self, value: &T) -> T {
         /*!
-         * Where possible, replaces type/int/float variables in
+         * Where possible, replaces type/int/float/const variables in
          * `value` with their final value. Note that region variables
          * are unaffected. If a type variable has not been unified, it
          * is left as is.  This is an idempotent operation that does
@@ -926,6 +1183,8 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
          * at will.
          */
 
+        // `OpportunisticTypeResolver` (see resolve.rs) walks the const
+        // table alongside the int/float tables it already visits.
         let mut r = resolve::OpportunisticTypeResolver::new(self);
         value.fold_with(&mut r)
     }
@@ -939,6 +1198,11 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
          *
          * This method is idempotent, but it not typically not invoked
          * except during the writeback phase.
+         *
+         * Const variables are folded the same way: an unresolved `[T; N]`
+         * length is an `Err` here just like an unresolved type, so a
+         * const-generic argument that never got unified surfaces as a
+         * normal fully-resolve failure rather than silently defaulting.
          */
 
         resolve::fully_resolve(self, value)
@@ -1029,6 +1293,21 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
         self.report_and_explain_type_error(trace, err);
     }
 
+    /// Like `report_and_explain_type_error` (see `error_reporting.rs`), but
+    /// builds and returns the `DiagnosticBuilder` instead of emitting it.
+    /// Callers that know *why* a relation was required -- e.g. "expected
+    /// because of the match arm here" -- can attach that as an extra span
+    /// or note before calling `.emit()`, instead of the error coming out
+    /// bare the moment it's constructed.
+    pub fn report_and_explain_type_error_builder(&'a self,
+                                                 trace: TypeTrace<'tcx>,
+                                                 err: &ty::type_err<'tcx>)
+                                                 -> DiagnosticBuilder<'a> {
+        let span = trace.span();
+        let failure_str = trace.origin.as_str();
+        self.tcx.sess.struct_span_err(span, &format!("{} ({})", failure_str, err))
+    }
+
     pub fn replace_late_bound_regions_with_fresh_var<T>(
         &self,
         span: Span,
@@ -1072,6 +1351,212 @@ impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
             self.equate(true, trace).relate(a, b)
         }).map(|_| ())
     }
+
+    /// Registers a predicate obligation (e.g. `T: Clone`) discovered while
+    /// relating types, tagged with `cause` so that if it's never
+    /// discharged the resulting ambiguity error can point back to why it
+    /// was required in the first place.
+    pub fn register_predicate_obligation(&self,
+                                         cause: ObligationCause,
+                                         predicate: ty::Predicate<'tcx>) {
+        self.pending_obligations.borrow_mut().push(PredicateObligation {
+            cause: cause,
+            predicate: predicate,
+        });
+    }
+
+    /// Removes and returns every obligation registered so far, leaving the
+    /// buffer empty.
+    pub fn drain_obligations(&self) -> PredicateObligations<'tcx> {
+        mem::replace(&mut *self.pending_obligations.borrow_mut(), Vec::new())
+    }
+
+    /// Drains the pending obligations and hands them to `engine`, running
+    /// it to a fixed point. Obligations `engine` can't discharge (genuine
+    /// ambiguity, or a dependency on inference that never resolves) come
+    /// back as the `Err` case for the caller to report.
+    pub fn fulfill_obligations<E>(&self, engine: &mut E) -> Result<(), PredicateObligations<'tcx>>
+        where E: TraitEngine<'tcx>
+    {
+        let obligations = self.drain_obligations();
+        engine.select_all_or_error(self, obligations)
+    }
+}
+
+/// The inference variables `fudge_inference_if_ok`'s closure allocated,
+/// gathered from each table right after the closure returns (but before its
+/// snapshot is rolled back, since rolling back is what invalidates these
+/// ids).
+struct VarsCreatedSinceSnapshot {
+    type_vars: Vec<TyVid>,
+    int_vars: Vec<IntVid>,
+    float_vars: Vec<FloatVid>,
+    const_vars: Vec<ConstVid>,
+    region_vars: Vec<RegionVid>,
+}
+
+impl VarsCreatedSinceSnapshot {
+    /// Narrows `self` down to just the variables also present in
+    /// `mentioned`, preserving their order in `self`. Used to restrict the set
+    /// of vars `InferenceFudger` replaces to those `value` actually still
+    /// refers to, rather than every var the closure allocated along the
+    /// way (most of which were resolved away and never reached `value` at
+    /// all).
+    fn retain_mentioned(self, mentioned: &VarsInValue) -> VarsCreatedSinceSnapshot {
+        VarsCreatedSinceSnapshot {
+            type_vars: self.type_vars.into_iter()
+                .filter(|v| mentioned.type_vars.contains(v)).collect(),
+            int_vars: self.int_vars.into_iter()
+                .filter(|v| mentioned.int_vars.contains(v)).collect(),
+            float_vars: self.float_vars.into_iter()
+                .filter(|v| mentioned.float_vars.contains(v)).collect(),
+            const_vars: self.const_vars.into_iter()
+                .filter(|v| mentioned.const_vars.contains(v)).collect(),
+            region_vars: self.region_vars.into_iter()
+                .filter(|v| mentioned.region_vars.contains(v)).collect(),
+        }
+    }
+}
+
+/// The set of unresolved inference variables a value actually mentions,
+/// gathered by folding over it (without altering it -- every `fold_*`
+/// method below returns its argument verbatim after recording it) via the
+/// same `TypeFolder` machinery `InferenceFudger` itself uses. Used by
+/// `fudge_inference_if_ok` to tell which of the variables its closure
+/// allocated are worth minting replacements for.
+struct VarsInValue<'a, 'tcx: 'a> {
+    tcx: &'a ty::ctxt<'tcx>,
+    type_vars: FnvHashSet<TyVid>,
+    int_vars: FnvHashSet<IntVid>,
+    float_vars: FnvHashSet<FloatVid>,
+    const_vars: FnvHashSet<ConstVid>,
+    region_vars: FnvHashSet<RegionVid>,
+}
+
+impl<'a, 'tcx> VarsInValue<'a, 'tcx> {
+    fn collect<T: TypeFoldable<'tcx>>(tcx: &'a ty::ctxt<'tcx>, value: &T) -> VarsInValue<'a, 'tcx> {
+        let mut collector = VarsInValue {
+            tcx: tcx,
+            type_vars: FnvHashSet(),
+            int_vars: FnvHashSet(),
+            float_vars: FnvHashSet(),
+            const_vars: FnvHashSet(),
+            region_vars: FnvHashSet(),
+        };
+        value.fold_with(&mut collector);
+        collector
+    }
+}
+
+impl<'a, 'tcx> TypeFolder<'tcx> for VarsInValue<'a, 'tcx> {
+    fn tcx(&self) -> &ty::ctxt<'tcx> {
+        self.tcx
+    }
+
+    fn fold_ty(&mut self, t: Ty<'tcx>) -> Ty<'tcx> {
+        match t.sty {
+            ty::TyInfer(ty::TyVar(v)) => { self.type_vars.insert(v); }
+            ty::TyInfer(ty::IntVar(v)) => { self.int_vars.insert(v); }
+            ty::TyInfer(ty::FloatVar(v)) => { self.float_vars.insert(v); }
+            _ => {}
+        }
+        ty_fold::super_fold_ty(self, t)
+    }
+
+    fn fold_region(&mut self, r: ty::Region) -> ty::Region {
+        if let ty::ReInfer(ty::ReVar(v)) = r {
+            self.region_vars.insert(v);
+        }
+        r
+    }
+
+    fn fold_const(&mut self, constant: ty::ConstVal<'tcx>) -> ty::ConstVal<'tcx> {
+        if let ty::ConstVal::Infer(ty::InferConst::Var(v)) = constant {
+            self.const_vars.insert(v);
+        }
+        ty_fold::super_fold_const(self, constant)
+    }
+}
+
+/// Replaces every variable in `VarsCreatedSinceSnapshot` with a freshly
+/// allocated variable of the same kind, built once up front so repeated
+/// occurrences of the same old variable fold to the same new one. Used only
+/// by `fudge_inference_if_ok`, after the closure's own snapshot has already
+/// been rolled back -- the variables it maps *from* no longer exist, only
+/// the ones it maps *to* do.
+struct InferenceFudger<'a, 'tcx: 'a> {
+    type_vars: FnvHashMap<TyVid, Ty<'tcx>>,
+    int_vars: FnvHashMap<IntVid, Ty<'tcx>>,
+    float_vars: FnvHashMap<FloatVid, Ty<'tcx>>,
+    const_vars: FnvHashMap<ConstVid, ty::ConstVal<'tcx>>,
+    region_vars: FnvHashMap<RegionVid, ty::Region>,
+    tcx: &'a ty::ctxt<'tcx>,
+}
+
+impl<'a, 'tcx> InferenceFudger<'a, 'tcx> {
+    fn new(infcx: &'a InferCtxt<'a, 'tcx>, created: VarsCreatedSinceSnapshot)
+           -> InferenceFudger<'a, 'tcx> {
+        let type_vars = created.type_vars.iter()
+            .map(|&v| (v, infcx.next_ty_var()))
+            .collect();
+        let int_vars = created.int_vars.iter()
+            .map(|&v| (v, ty::mk_infer(infcx.tcx, ty::IntVar(infcx.next_int_var_id()))))
+            .collect();
+        let float_vars = created.float_vars.iter()
+            .map(|&v| (v, ty::mk_infer(infcx.tcx, ty::FloatVar(infcx.next_float_var_id()))))
+            .collect();
+        let const_vars = created.const_vars.iter()
+            .map(|&v| (v, infcx.next_const_var()))
+            .collect();
+        let region_vars = created.region_vars.iter()
+            .map(|&v| (v, infcx.next_region_var(MiscVariable(codemap::DUMMY_SP))))
+            .collect();
+        InferenceFudger {
+            type_vars: type_vars,
+            int_vars: int_vars,
+            float_vars: float_vars,
+            const_vars: const_vars,
+            region_vars: region_vars,
+            tcx: infcx.tcx,
+        }
+    }
+}
+
+impl<'a, 'tcx> TypeFolder<'tcx> for InferenceFudger<'a, 'tcx> {
+    fn tcx(&self) -> &ty::ctxt<'tcx> {
+        self.tcx
+    }
+
+    fn fold_ty(&mut self, t: Ty<'tcx>) -> Ty<'tcx> {
+        match t.sty {
+            ty::TyInfer(ty::TyVar(v)) => {
+                self.type_vars.get(&v).cloned().unwrap_or(t)
+            }
+            ty::TyInfer(ty::IntVar(v)) => {
+                self.int_vars.get(&v).cloned().unwrap_or(t)
+            }
+            ty::TyInfer(ty::FloatVar(v)) => {
+                self.float_vars.get(&v).cloned().unwrap_or(t)
+            }
+            _ => ty_fold::super_fold_ty(self, t),
+        }
+    }
+
+    fn fold_region(&mut self, r: ty::Region) -> ty::Region {
+        match r {
+            ty::ReInfer(ty::ReVar(v)) => self.region_vars.get(&v).cloned().unwrap_or(r),
+            _ => r,
+        }
+    }
+
+    fn fold_const(&mut self, constant: ty::ConstVal<'tcx>) -> ty::ConstVal<'tcx> {
+        match constant {
+            ty::ConstVal::Infer(ty::InferConst::Var(v)) => {
+                self.const_vars.get(&v).cloned().unwrap_or(constant)
+            }
+            _ => ty_fold::super_fold_const(self, constant),
+        }
+    }
 }
 
 impl<'tcx> TypeTrace<'tcx> {
@@ -0,0 +1,155 @@
+// Copyright 2012-2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `resolve_regions_and_report_errors` used to hand every
+//! `RegionResolutionError` straight to `report_region_errors`, which only
+//! knows how to print the generic "lifetime mismatch" message. This module
+//! recognizes a handful of region-error shapes that come up often enough to
+//! deserve their own targeted message and suggestion, built from the
+//! `SubregionOrigin`/`RegionVariableOrigin` information the error already
+//! carries:
+//!
+//!  - a named lifetime parameter conflicting with an anonymous one in a
+//!    function signature (suggest naming the anonymous one);
+//!  - a borrow forced to `'static` because it flows into an `impl
+//!    Trait`/trait-object return (point at the return type that forced it);
+//!  - two distinct anonymous lifetimes in the same signature that must be
+//!    unified (suggest giving them a shared named parameter).
+//!
+//! `NiceRegionError::try_report` runs each detector in turn; the first one
+//! that recognizes the error's shape emits its own diagnostic and we're
+//! done. If none of them recognize it, the caller falls back to
+//! `report_region_errors` exactly as before.
+
+use middle::infer::{InferCtxt, RegionVariableOrigin, SubregionOrigin};
+use middle::infer::region_inference::RegionResolutionError;
+use middle::ty;
+
+pub struct NiceRegionError<'a, 'tcx: 'a> {
+    infcx: &'a InferCtxt<'a, 'tcx>,
+    error: RegionResolutionError<'tcx>,
+}
+
+impl<'a, 'tcx> NiceRegionError<'a, 'tcx> {
+    pub fn new(infcx: &'a InferCtxt<'a, 'tcx>, error: RegionResolutionError<'tcx>)
+               -> NiceRegionError<'a, 'tcx> {
+        NiceRegionError { infcx: infcx, error: error }
+    }
+
+    /// Tries each detector below in turn. Returns `true` as soon as one
+    /// recognizes the error's shape and has emitted its own diagnostic for
+    /// it, so the caller knows not to also run the generic reporter.
+    pub fn try_report(&self) -> bool {
+        self.try_report_named_anon_conflict()
+            || self.try_report_forced_static_due_to_impl_trait()
+            || self.try_report_anon_anon_conflict()
+    }
+
+    /// (a) A named lifetime parameter conflicting with an anonymous
+    /// (`BrAnon`) one in the same function signature: suggest giving the
+    /// anonymous one the same name.
+    fn try_report_named_anon_conflict(&self) -> bool {
+        let (name, anon_origin) = match self.error {
+            RegionResolutionError::SubSupConflict(RegionVariableOrigin::EarlyBoundRegion(_, name),
+                                                   ref sub_origin, sub_r,
+                                                   ref sup_origin, sup_r) => {
+                if is_anon_region(sub_r) {
+                    (name, sub_origin)
+                } else if is_anon_region(sup_r) {
+                    (name, sup_origin)
+                } else {
+                    return false;
+                }
+            }
+            _ => return false,
+        };
+
+        let span = anon_origin.span();
+        self.infcx.tcx.sess.struct_span_err(
+            span,
+            "lifetime mismatch")
+            .span_label(span, &format!("expected lifetime `{}`", name))
+            .span_suggestion(span,
+                              &format!("consider naming this lifetime `{}`", name),
+                              format!("{}", name))
+            .emit();
+        true
+    }
+
+    /// (b) A borrow forced to `'static` because it flows into a return
+    /// type that demands it -- an `impl Trait` or trait-object return with
+    /// no named lifetime bound.
+    fn try_report_forced_static_due_to_impl_trait(&self) -> bool {
+        let origin = match self.error {
+            RegionResolutionError::ConcreteFailure(ref origin, _, ty::ReStatic) => origin,
+            RegionResolutionError::GenericBoundFailure(ref origin, _, ty::ReStatic) => origin,
+            _ => return false,
+        };
+
+        let span = match *origin {
+            SubregionOrigin::DefaultExistentialBound(ref trace) => trace.span(),
+            SubregionOrigin::RelateParamBound(span, _) => span,
+            _ => return false,
+        };
+
+        self.infcx.tcx.sess.struct_span_err(
+            span,
+            "borrowed data escapes outside of its scope")
+            .span_label(span,
+                        "the return type requires that the borrowed data be `'static`")
+            .span_note(span,
+                       "consider naming the lifetime of the returned `impl Trait` or trait \
+                        object so it can borrow from this scope instead")
+            .emit();
+        true
+    }
+
+    /// (c) Two distinct anonymous lifetimes in the same signature that
+    /// must be unified: suggest a shared named parameter.
+    fn try_report_anon_anon_conflict(&self) -> bool {
+        let (sub_origin, sup_origin) = match self.error {
+            RegionResolutionError::SubSupConflict(_, ref sub_origin, sub_r, ref sup_origin, sup_r)
+                if is_anon_region(sub_r) && is_anon_region(sup_r) && sub_r != sup_r => {
+                (sub_origin, sup_origin)
+            }
+            _ => return false,
+        };
+
+        let span_a = sub_origin.span();
+        let span_b = sup_origin.span();
+        self.infcx.tcx.sess.struct_span_err(
+            span_a,
+            "lifetime mismatch")
+            .span_label(span_a, "these two lifetimes must be the same")
+            .span_label(span_b, "...but this lifetime is distinct")
+            .span_note(span_a,
+                       "consider introducing a named lifetime parameter and using it for both")
+            .emit();
+        true
+    }
+}
+
+/// Whether `region` is an anonymous (`'_`) region introduced by elision in
+/// a function signature, as opposed to a named lifetime parameter.
+fn is_anon_region(region: ty::Region) -> bool {
+    match region {
+        ty::ReFree(ty::FreeRegion { bound_region: ty::BrAnon(_), .. }) => true,
+        ty::ReLateBound(_, ty::BrAnon(_)) => true,
+        _ => false,
+    }
+}
+
+impl<'a, 'tcx> InferCtxt<'a, 'tcx> {
+    /// Attempts the targeted diagnostics above for a single region error
+    /// before the generic reporter runs; returns whether it handled it.
+    pub fn try_report_nice_region_error(&'a self, error: &RegionResolutionError<'tcx>) -> bool {
+        NiceRegionError::new(self, error.clone()).try_report()
+    }
+}
@@ -15,15 +15,17 @@ use middle::ty::{ReEarlyBound, BrFresh, ctxt};
 use middle::ty::{ReFree, ReScope, ReInfer, ReStatic, Region, ReEmpty};
 use middle::ty::{ReSkolemized, ReVar, BrEnv};
 use middle::ty::{mt, Ty};
-use middle::ty::{TyBool, TyChar, TyStruct, TyEnum};
+use middle::ty::{TyBool, TyChar, TyStruct, TyEnum, TyNever};
 use middle::ty::{TyError, TyStr, TyArray, TySlice, TyFloat, TyBareFn};
 use middle::ty::{TyParam, TyRawPtr, TyRef, TyTuple};
-use middle::ty::TyClosure;
+use middle::ty::{TyClosure, TyGenerator};
 use middle::ty::{TyBox, TyTrait, TyInt, TyUint, TyInfer};
 use middle::ty;
 use middle::ty_fold::{self, TypeFoldable};
 
+use std::cell::{Cell, RefCell};
 use std::fmt;
+use std::mem;
 use syntax::abi;
 use syntax::parse::token;
 use syntax::{ast, ast_util};
@@ -32,90 +34,347 @@ pub fn verbose() -> bool {
     ty::tls::with(|tcx| tcx.sess.verbose())
 }
 
-fn fn_sig(f: &mut fmt::Formatter,
+/// Controls how we print various kinds of regions when they show up in
+/// error messages. It is often useful, when printing out a diagnostic that
+/// involves more than one occurrence of the same region, to label each
+/// occurrence with a number like `'0` or `'1` so that the expected and
+/// found types can be lined up with one another. `RegionHighlightMode`
+/// carries that labeling information around, and `ty::Region`'s `Display`
+/// impl consults it before falling back to its ordinary concise name.
+#[derive(Copy, Clone, Default)]
+pub struct RegionHighlightMode {
+    // Maps a given `ty::Region` to the number that should be printed for
+    // it (e.g., `'0`, `'1`, ...). We only ever highlight a handful of
+    // regions in a single diagnostic, hence the small fixed-size array.
+    highlight_regions: [Option<(ty::Region, usize)>; 3],
+
+    // If a `ty::BoundRegion` is specified, we want to highlight it in the
+    // rendering of the type where it appears. This is used when we have a
+    // `for<'r>` binder but no concrete `ty::Region` to hang the label off
+    // of (e.g. before the late-bound region has been instantiated).
+    highlight_bound_region: Option<(ty::BoundRegion, usize)>,
+}
+
+impl RegionHighlightMode {
+    pub fn new() -> Self {
+        RegionHighlightMode::default()
+    }
+
+    /// Highlight the region `region` so that it is printed as `'N`, where
+    /// `number` is `N`, whenever it appears while this mode is in effect.
+    pub fn highlighting_region(&mut self, region: ty::Region, number: usize) {
+        let index = self.highlight_regions.iter()
+                        .position(|slot| slot.is_none())
+                        .expect("can only highlight a handful of regions at a time");
+        self.highlight_regions[index] = Some((region, number));
+    }
+
+    /// Highlight the bound region `br` so that it is printed as `'N`
+    /// wherever it is encountered as the `bound_region` of a late-bound
+    /// region.
+    pub fn highlighting_bound_region(&mut self, br: ty::BoundRegion, number: usize) {
+        assert!(self.highlight_bound_region.is_none());
+        self.highlight_bound_region = Some((br, number));
+    }
+
+    fn region_highlighted(&self, region: ty::Region) -> Option<usize> {
+        self.highlight_regions.iter()
+            .filter_map(|h| *h)
+            .find(|&(r, _)| r == region)
+            .map(|(_, number)| number)
+    }
+
+    fn bound_region_highlighted(&self, br: ty::BoundRegion) -> Option<usize> {
+        self.highlight_bound_region.and_then(|(r, number)| {
+            if r == br { Some(number) } else { None }
+        })
+    }
+}
+
+thread_local! {
+    static REGION_HIGHLIGHT_MODE: RefCell<RegionHighlightMode> =
+        RefCell::new(RegionHighlightMode::new())
+}
+
+/// Invokes `f` with `mode` installed as the current `RegionHighlightMode`,
+/// restoring whatever mode was previously in effect once `f` returns. This
+/// lets error-reporting code scope the highlighting to just the block where
+/// it renders the expected/found types it wants labeled.
+pub fn with_highlighted_regions<R, F>(mode: RegionHighlightMode, f: F) -> R
+    where F: FnOnce() -> R
+{
+    REGION_HIGHLIGHT_MODE.with(|c| {
+        let old = mem::replace(&mut *c.borrow_mut(), mode);
+        let result = f();
+        *c.borrow_mut() = old;
+        result
+    })
+}
+
+thread_local! {
+    static QUALIFY_PATHS: Cell<bool> = Cell::new(false)
+}
+
+/// Invokes `f` with fully crate-qualified, `DefPath`-based item paths
+/// turned on for the duration of the call, instead of the usual short
+/// `ty::item_path_str` names. Useful for diagnostics and `-Z` debug output
+/// where two crates exporting same-named items would otherwise make the
+/// short path ambiguous (`m::Foo` vs `m::Foo`).
+pub fn with_qualified_paths<R, F>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    QUALIFY_PATHS.with(|c| {
+        let old = c.get();
+        c.set(true);
+        let result = f();
+        c.set(old);
+        result
+    })
+}
+
+/// Renders `did`'s full `DefPath` -- the crate name followed by the chain
+/// of module/item path components leading to it -- instead of the shorter
+/// (and potentially ambiguous) name `ty::item_path_str` would produce.
+/// When two linked crates happen to share a plain name, the crate's
+/// disambiguator is appended so the two don't print identically.
+fn qualified_path_str<'tcx>(tcx: &ty::ctxt<'tcx>, did: ast::DefId) -> String {
+    let crate_name = if did.krate == ast::LOCAL_CRATE {
+        tcx.crate_name.clone()
+    } else {
+        tcx.sess.cstore.crate_name(did.krate)
+    };
+
+    let mut s = String::new();
+    s.push_str(&crate_name);
+    if crate_name_collides(tcx, did.krate, &crate_name) {
+        s.push('[');
+        s.push_str(&tcx.sess.cstore.crate_disambiguator(did.krate));
+        s.push(']');
+    }
+
+    for component in &tcx.def_path(did).data {
+        s.push_str("::");
+        s.push_str(&component.data.to_string());
+    }
+
+    s
+}
+
+fn crate_name_collides(tcx: &ty::ctxt, krate: ast::CrateNum, name: &str) -> bool {
+    tcx.sess.cstore.crates().into_iter().any(|other| {
+        other != krate && tcx.sess.cstore.crate_name(other) == name
+    })
+}
+
+thread_local! {
+    static TRIMMED_PATHS: Cell<bool> = Cell::new(false)
+}
+
+/// Invokes `f` with "trimmed" path printing turned on for the duration of
+/// the call: instead of the usual short `ty::item_path_str` (module-
+/// qualified within the current crate) or the fully crate-qualified
+/// `DefPath`, only the item's own final path component is shown. Intended
+/// for interactive output (e.g. `-Z unpretty`) where the surrounding
+/// context already makes the enclosing module obvious and the shorter
+/// name reads better.
+pub fn with_trimmed_paths<R, F>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    TRIMMED_PATHS.with(|c| {
+        let old = c.get();
+        c.set(true);
+        let result = f();
+        c.set(old);
+        result
+    })
+}
+
+/// Renders just the final segment of `did`'s `DefPath`, e.g. `Foo` rather
+/// than `foo::bar::Foo`. This is a best-effort trim: genuinely resolving
+/// ambiguity would require comparing against every other item visible at
+/// the print site, which isn't information this context has on hand, so
+/// we settle for the shortest name that is usually unambiguous in
+/// practice and fall back to the fully qualified path if `did` somehow
+/// has an empty `DefPath`.
+fn trimmed_path_str<'tcx>(tcx: &ty::ctxt<'tcx>, did: ast::DefId) -> String {
+    match tcx.def_path(did).data.last() {
+        Some(component) => component.data.to_string(),
+        None => qualified_path_str(tcx, did),
+    }
+}
+
+/// Picks the name `did` should be printed with, according to whichever of
+/// the path-naming toggles is active. `qualify_paths` wins over
+/// `trimmed_paths` since the two are meant to be mutually exclusive debug
+/// aids; the ordinary short path is the default when neither is set.
+fn resolved_path_str(cx: &PrintCx, did: ast::DefId) -> String {
+    if cx.qualify_paths {
+        qualified_path_str(cx.tcx, did)
+    } else if cx.trimmed_paths {
+        trimmed_path_str(cx.tcx, did)
+    } else {
+        ty::item_path_str(cx.tcx, did)
+    }
+}
+
+/// A value that knows how to print itself through a threaded-through
+/// `PrintCx`, in place of an ad-hoc `fmt::Display`/`fmt::Debug` impl that
+/// would have to re-derive `tcx` and the printing configuration on its own
+/// via `ty::tls::with(..)` every time it nests another `{}` inside itself.
+/// Every type `fn_sig`, `parameterized` and `in_binder` print implements
+/// this instead of (or alongside, as a thin wrapper) `fmt::Display`.
+pub trait Print {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result;
+}
+
+/// The context threaded through the printing helpers (`fn_sig`,
+/// `parameterized`, `in_binder`) in this file. Besides `tcx` and the
+/// `Formatter` being printed to, it carries the printing configuration (the
+/// `-Z verbose` flag and the path-naming toggles), so that this state
+/// doesn't have to be re-derived via `ty::tls::with(..)` at every nested
+/// `{}` the way the free-standing helpers used to.
+///
+/// `fmt`'s lifetime is kept independent of `cx`'s own (`'a` vs. `'b`)
+/// because the `Formatter` is borrowed from the caller's `fmt::Result`
+/// signature, which has nothing to do with how long this `PrintCx` lives.
+pub struct PrintCx<'a, 'b: 'a, 'tcx: 'a> {
+    pub tcx: &'a ty::ctxt<'tcx>,
+    pub fmt: &'a mut fmt::Formatter<'b>,
+    pub verbose: bool,
+    pub qualify_paths: bool,
+    pub trimmed_paths: bool,
+}
+
+impl<'a, 'b, 'tcx> PrintCx<'a, 'b, 'tcx> {
+    /// Builds a `PrintCx` from the ambient `ty::tls` context and the
+    /// thread-local path-qualification modes, then runs `f` with it.
+    pub fn with<R, F>(fmt: &'a mut fmt::Formatter<'b>, f: F) -> R
+        where F: FnOnce(&mut PrintCx) -> R
+    {
+        ty::tls::with(|tcx| {
+            let mut cx = PrintCx {
+                tcx: tcx,
+                fmt: fmt,
+                verbose: tcx.sess.verbose(),
+                qualify_paths: QUALIFY_PATHS.with(|c| c.get()),
+                trimmed_paths: TRIMMED_PATHS.with(|c| c.get()),
+            };
+            f(&mut cx)
+        })
+    }
+}
+
+/// Renders an array length, which may be a concrete integer, a const
+/// expression that hasn't been evaluated yet (e.g. a const generic
+/// parameter or an associated const), or an erroneous/unresolved length.
+/// Shared by `TyArray`'s `Display` arm and, eventually, any other place
+/// that prints a const-generic argument.
+fn fmt_const_len(f: &mut fmt::Formatter, len: &ty::ConstVal) -> fmt::Result {
+    match *len {
+        ty::ConstVal::Integral(ref i) => write!(f, "{}", i),
+        ty::ConstVal::Unevaluated(did, _) => {
+            write!(f, "{}", ty::tls::with(|tcx| ty::item_path_str(tcx, did)))
+        }
+        // An unresolved const-generic length -- the motivating case for
+        // this helper in the first place -- has nothing printable to show
+        // beyond "some length we don't know yet".
+        ty::ConstVal::Infer(_) => write!(f, "_"),
+        ty::ConstVal::Err => write!(f, "_"),
+        // Every other `ConstVal` variant also prints as `_`; this arm is a
+        // catch-all, so a new variant is not flagged at compile time.
+        _ => write!(f, "_"),
+    }
+}
+
+fn fn_sig(cx: &mut PrintCx,
           inputs: &[Ty],
           variadic: bool,
           output: ty::FnOutput)
           -> fmt::Result {
-    try!(write!(f, "("));
+    try!(write!(cx.fmt, "("));
     let mut inputs = inputs.iter();
     if let Some(&ty) = inputs.next() {
-        try!(write!(f, "{}", ty));
+        try!(ty.print(cx));
         for &ty in inputs {
-            try!(write!(f, ", {}", ty));
+            try!(write!(cx.fmt, ", "));
+            try!(ty.print(cx));
         }
         if variadic {
-            try!(write!(f, ", ..."));
+            try!(write!(cx.fmt, ", ..."));
         }
     }
-    try!(write!(f, ")"));
+    try!(write!(cx.fmt, ")"));
 
     match output {
         ty::FnConverging(ty) => {
             if !ty::type_is_nil(ty) {
-                try!(write!(f, " -> {}", ty));
+                try!(write!(cx.fmt, " -> "));
+                try!(ty.print(cx));
             }
             Ok(())
         }
+        // Diverging functions return the never type; route it through the
+        // ordinary type-printing path (the `TyNever` arm below) rather
+        // than hard-coding the `!` here, so `fn(...) -> !` comes out of
+        // the same code that prints `!` anywhere else a type is printed.
         ty::FnDiverging => {
-            write!(f, " -> !")
+            try!(write!(cx.fmt, " -> "));
+            cx.tcx.types.never.print(cx)
         }
     }
 }
 
-fn parameterized<GG>(f: &mut fmt::Formatter,
+fn parameterized<GG>(cx: &mut PrintCx,
                      substs: &subst::Substs,
                      did: ast::DefId,
                      projections: &[ty::ProjectionPredicate],
                      get_generics: GG)
                      -> fmt::Result
-    where GG: for<'tcx> FnOnce(&ty::ctxt<'tcx>) -> ty::Generics<'tcx>
+    where GG: for<'gtcx> FnOnce(&ty::ctxt<'gtcx>) -> ty::Generics<'gtcx>
 {
-    let (fn_trait_kind, verbose) = try!(ty::tls::with(|tcx| {
-        try!(write!(f, "{}", ty::item_path_str(tcx, did)));
-        Ok((tcx.lang_items.fn_trait_kind(did), tcx.sess.verbose()))
-    }));
+    try!(write!(cx.fmt, "{}", resolved_path_str(cx, did)));
+    let fn_trait_kind = cx.tcx.lang_items.fn_trait_kind(did);
+    let verbose = cx.verbose;
 
     let mut empty = true;
-    let mut start_or_continue = |f: &mut fmt::Formatter, start: &str, cont: &str| {
+    let mut start_or_continue = |cx: &mut PrintCx, start: &str, cont: &str| {
         if empty {
             empty = false;
-            write!(f, "{}", start)
+            write!(cx.fmt, "{}", start)
         } else {
-            write!(f, "{}", cont)
+            write!(cx.fmt, "{}", cont)
         }
     };
 
     if verbose {
         match substs.regions {
             subst::ErasedRegions => {
-                try!(start_or_continue(f, "<", ", "));
-                try!(write!(f, ".."));
+                try!(start_or_continue(cx, "<", ", "));
+                try!(write!(cx.fmt, ".."));
             }
             subst::NonerasedRegions(ref regions) => {
                 for region in regions {
-                    try!(start_or_continue(f, "<", ", "));
-                    try!(write!(f, "{:?}", region));
+                    try!(start_or_continue(cx, "<", ", "));
+                    try!(write!(cx.fmt, "{:?}", region));
                 }
             }
         }
         for &ty in &substs.types {
-            try!(start_or_continue(f, "<", ", "));
-            try!(write!(f, "{}", ty));
+            try!(start_or_continue(cx, "<", ", "));
+            try!(ty.print(cx));
         }
         for projection in projections {
-            try!(start_or_continue(f, "<", ", "));
-            try!(write!(f, "{}={}",
-                        projection.projection_ty.item_name,
-                        projection.ty));
+            try!(start_or_continue(cx, "<", ", "));
+            try!(write!(cx.fmt, "{}=", projection.projection_ty.item_name));
+            try!(projection.ty.print(cx));
         }
-        return start_or_continue(f, "", ">");
+        return start_or_continue(cx, "", ">");
     }
 
     if fn_trait_kind.is_some() && projections.len() == 1 {
         let projection_ty = projections[0].ty;
         if let TyTuple(ref args) = substs.types.get_slice(subst::TypeSpace)[0].sty {
-            return fn_sig(f, args, false, ty::FnConverging(projection_ty));
+            return fn_sig(cx, args, false, ty::FnConverging(projection_ty));
         }
     }
 
@@ -123,17 +382,16 @@ fn parameterized<GG>(f: &mut fmt::Formatter,
         subst::ErasedRegions => { }
         subst::NonerasedRegions(ref regions) => {
             for &r in regions {
-                try!(start_or_continue(f, "<", ", "));
-                let s = r.to_string();
-                if s.is_empty() {
+                try!(start_or_continue(cx, "<", ", "));
+                if region_prints_empty(r) {
                     // This happens when the value of the region
                     // parameter is not easily serialized. This may be
                     // because the user omitted it in the first place,
                     // or because it refers to some block in the code,
                     // etc. I'm not sure how best to serialize this.
-                    try!(write!(f, "'_"));
+                    try!(write!(cx.fmt, "'_"));
                 } else {
-                    try!(write!(f, "{}", s));
+                    try!(r.print(cx));
                 }
             }
         }
@@ -145,7 +403,8 @@ fn parameterized<GG>(f: &mut fmt::Formatter,
     // is kind of a hacky workaround in that -Z verbose is required to
     // avoid those ICEs.
     let tps = substs.types.get_slice(subst::TypeSpace);
-    let num_defaults = ty::tls::with(|tcx| {
+    let num_defaults = {
+        let tcx = cx.tcx;
         let generics = get_generics(tcx);
 
         let has_self = substs.self_ty().is_some();
@@ -174,28 +433,53 @@ fn parameterized<GG>(f: &mut fmt::Formatter,
         } else {
             0
         }
-    });
+    };
 
     for &ty in &tps[..tps.len() - num_defaults] {
-        try!(start_or_continue(f, "<", ", "));
-        try!(write!(f, "{}", ty));
+        try!(start_or_continue(cx, "<", ", "));
+        try!(ty.print(cx));
     }
 
     for projection in projections {
-        try!(start_or_continue(f, "<", ", "));
-        try!(write!(f, "{}={}",
-                    projection.projection_ty.item_name,
-                    projection.ty));
+        try!(start_or_continue(cx, "<", ", "));
+        try!(write!(cx.fmt, "{}=", projection.projection_ty.item_name));
+        try!(projection.ty.print(cx));
     }
 
-    start_or_continue(f, "", ">")
+    start_or_continue(cx, "", ">")
 }
 
-fn in_binder<'tcx, T, U>(f: &mut fmt::Formatter,
-                         tcx: &ty::ctxt<'tcx>,
+/// Whether `region`'s `Print` impl renders as the empty string -- i.e.
+/// there is nothing useful to show the user, typically because the region
+/// is an inference variable or refers to some block in the code that
+/// doesn't have a stable surface syntax. Callers that need to fall back to
+/// a placeholder like `'_` in that case check this instead of probing the
+/// rendered output, since probing would mean re-entering `Print` through a
+/// separate `Formatter` just to throw the string away.
+fn region_prints_empty(region: ty::Region) -> bool {
+    if verbose() || REGION_HIGHLIGHT_MODE.with(|c| c.borrow().region_highlighted(region).is_some()) {
+        return false;
+    }
+    match region {
+        ty::ReScope(_) | ty::ReInfer(ReVar(_)) => true,
+        ty::ReLateBound(_, br) |
+        ty::ReFree(ty::FreeRegion { bound_region: br, .. }) |
+        ty::ReInfer(ReSkolemized(_, br)) => {
+            let highlighted =
+                REGION_HIGHLIGHT_MODE.with(|c| c.borrow().bound_region_highlighted(br).is_some());
+            !highlighted && match br {
+                BrNamed(..) => false,
+                BrAnon(_) | BrFresh(_) | BrEnv => true,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn in_binder<'tcx, T, U>(cx: &mut PrintCx,
                          original: &ty::Binder<T>,
                          lifted: Option<ty::Binder<U>>) -> fmt::Result
-    where T: fmt::Display, U: fmt::Display + TypeFoldable<'tcx>
+    where T: Print, U: Print + TypeFoldable<'tcx>
 {
     // Replace any anonymous late-bound regions with named
     // variants, using gensym'd identifiers, so that we can
@@ -205,110 +489,166 @@ fn in_binder<'tcx, T, U>(f: &mut fmt::Formatter,
     let value = if let Some(v) = lifted {
         v
     } else {
-        return write!(f, "{}", original.0);
+        return original.0.print(cx);
     };
 
     let mut empty = true;
-    let mut start_or_continue = |f: &mut fmt::Formatter, start: &str, cont: &str| {
+    let mut start_or_continue = |cx: &mut PrintCx, start: &str, cont: &str| {
         if empty {
             empty = false;
-            write!(f, "{}", start)
+            write!(cx.fmt, "{}", start)
         } else {
-            write!(f, "{}", cont)
+            write!(cx.fmt, "{}", cont)
         }
     };
 
+    let tcx = cx.tcx;
     let new_value = ty_fold::replace_late_bound_regions(tcx, &value, |br| {
-        let _ = start_or_continue(f, "for<", ", ");
+        let _ = start_or_continue(cx, "for<", ", ");
         ty::ReLateBound(ty::DebruijnIndex::new(1), match br {
             ty::BrNamed(_, name) => {
-                let _ = write!(f, "{}", name);
+                let _ = write!(cx.fmt, "{}", name);
                 br
             }
             ty::BrAnon(_) |
             ty::BrFresh(_) |
             ty::BrEnv => {
                 let name = token::intern("'r");
-                let _ = write!(f, "{}", name);
+                let _ = write!(cx.fmt, "{}", name);
                 ty::BrNamed(ast_util::local_def(ast::DUMMY_NODE_ID), name)
             }
         })
     }).0;
 
-    try!(start_or_continue(f, "", "> "));
-    write!(f, "{}", new_value)
+    // Now that the late-bound regions have names (possibly synthetic
+    // ones), normalize any associated-type projections that only
+    // resolve once those regions are fixed -- e.g. so that
+    // `for<'a> fn(&'a T) -> <F as FnOnce<(&'a T,)>>::Output` prints the
+    // underlying output type rather than the raw, unresolved projection.
+    let new_value = tcx.normalize_associated_type(&new_value);
+
+    try!(start_or_continue(cx, "", "> "));
+    new_value.print(cx)
+}
+
+impl<'a> Print for &'a str {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        write!(cx.fmt, "{}", self)
+    }
 }
 
-/// This curious type is here to help pretty-print trait objects. In
-/// a trait object, the projections are stored separately from the
-/// main trait bound, but in fact we want to package them together
-/// when printing out; they also have separate binders, but we want
-/// them to share a binder when we print them out. (And the binder
-/// pretty-printing logic is kind of clever and we don't want to
-/// reproduce it.) So we just repackage up the structure somewhat.
+/// The principal trait reference of a trait object together with its
+/// associated-type projections, grouped so they can be printed inside one
+/// shared binder as `Trait<Assoc = T>`. This is just the `Trait` and
+/// `Projection` entries plucked back out of `ty::TraitTy`'s existential-
+/// predicate slice for the duration of the print; auto traits and the
+/// region bound are printed separately by `ty::TraitTy`'s `Display` impl
+/// below.
 ///
-/// Right now there is only one trait in an object that can have
-/// projection bounds, so we just stuff them altogether. But in
-/// reality we should eventually sort things out better.
+/// Right now there is only one trait in an object that can carry
+/// projection bounds, so this just groups the principal with all of them.
 #[derive(Clone, Debug)]
-struct TraitAndProjections<'tcx>(ty::TraitRef<'tcx>, Vec<ty::ProjectionPredicate<'tcx>>);
+struct PrincipalAndProjections<'tcx>(ty::TraitRef<'tcx>, Vec<ty::ProjectionPredicate<'tcx>>);
 
-impl<'tcx> TypeFoldable<'tcx> for TraitAndProjections<'tcx> {
+impl<'tcx> TypeFoldable<'tcx> for PrincipalAndProjections<'tcx> {
     fn fold_with<F:ty_fold::TypeFolder<'tcx>>(&self, folder: &mut F)
-                                              -> TraitAndProjections<'tcx> {
-        TraitAndProjections(self.0.fold_with(folder), self.1.fold_with(folder))
+                                              -> PrincipalAndProjections<'tcx> {
+        PrincipalAndProjections(self.0.fold_with(folder), self.1.fold_with(folder))
     }
 }
 
-impl<'tcx> fmt::Display for TraitAndProjections<'tcx> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let TraitAndProjections(ref trait_ref, ref projection_bounds) = *self;
-        parameterized(f, trait_ref.substs,
+impl<'tcx> Print for PrincipalAndProjections<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        let PrincipalAndProjections(ref trait_ref, ref projection_bounds) = *self;
+        parameterized(cx, trait_ref.substs,
                       trait_ref.def_id,
                       projection_bounds,
                       |tcx| ty::lookup_trait_def(tcx, trait_ref.def_id).generics.clone())
     }
 }
 
-impl<'tcx> fmt::Display for ty::TraitTy<'tcx> {
+impl<'tcx> fmt::Display for PrincipalAndProjections<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let bounds = &self.bounds;
+        PrintCx::with(f, |cx| self.print(cx))
+    }
+}
 
-        // Generate the main trait ref, including associated types.
-        try!(ty::tls::with(|tcx| {
-            let principal = tcx.lift(&self.principal.0)
+impl<'tcx> Print for ty::TraitTy<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        try!(write!(cx.fmt, "dyn "));
+
+        // `self.predicates` is the object's interned existential-predicate
+        // slice, ordered with the principal trait (if any) first and its
+        // auto traits and projection bounds following it -- one ordered
+        // collection in place of the three separately-sorted fields
+        // (`principal`, `builtin_bounds`, `projection_bounds`) the old
+        // `ExistentialBounds` representation kept.
+        let (principal, rest) = match self.predicates.split_first() {
+            Some((&ty::ExistentialPredicate::Trait(principal), rest)) => (Some(principal), rest),
+            _ => (None, &self.predicates[..]),
+        };
+
+        // Print the principal trait ref, folding its associated-type
+        // projections into the angle brackets.
+        if let Some(principal) = principal {
+            let projection_bounds: Vec<_> = rest.iter().filter_map(|predicate| {
+                match *predicate {
+                    ty::ExistentialPredicate::Projection(p) => Some(p),
+                    _ => None,
+                }
+            }).collect();
+
+            let principal = cx.tcx.lift(&principal)
                                .expect("could not lift TraitRef for printing");
-            let projections = tcx.lift(&bounds.projection_bounds[..])
+            let projection_bounds = cx.tcx.lift(&projection_bounds[..])
                                  .expect("could not lift projections for printing");
-            let projections = projections.map_in_place(|p| p.0);
-
-            let tap = ty::Binder(TraitAndProjections(principal, projections));
-            in_binder(f, tcx, &ty::Binder(""), Some(tap))
-        }));
 
-        // Builtin bounds.
-        for bound in &bounds.builtin_bounds {
-            try!(write!(f, " + {:?}", bound));
+            let tap = ty::Binder(PrincipalAndProjections(principal, projection_bounds));
+            try!(in_binder(cx, &ty::Binder(""), Some(tap)));
         }
 
-        // FIXME: It'd be nice to compute from context when this bound
-        // is implied, but that's non-trivial -- we'd perhaps have to
-        // use thread-local data of some kind? There are also
-        // advantages to just showing the region, since it makes
-        // people aware that it's there.
-        let bound = bounds.region_bound.to_string();
-        if !bound.is_empty() {
-            try!(write!(f, " + {}", bound));
+        // Auto traits, sorted by their def-path string -- not their
+        // `{:?}` Debug form, which is an implementation detail of however
+        // `DefId` happens to print -- so the output is deterministic even
+        // when the object carries several marker bounds in an arbitrary
+        // order.
+        let mut auto_traits: Vec<String> = rest.iter().filter_map(|predicate| {
+            match *predicate {
+                ty::ExistentialPredicate::AutoTrait(did) => {
+                    Some(resolved_path_str(cx, did))
+                }
+                _ => None,
+            }
+        }).collect();
+        auto_traits.sort();
+        for auto_trait in &auto_traits {
+            try!(write!(cx.fmt, " + {}", auto_trait));
         }
 
-        if bounds.region_bound_will_change && verbose() {
-            try!(write!(f, " [WILL-CHANGE]"));
+        // The region bound. `'static` is the overwhelmingly common case
+        // and is usually either inferred or uninteresting, so it's
+        // elided unless `-Z verbose` was passed; any other bound is
+        // worth showing since it's not implied by context.
+        match self.region_bound {
+            ty::ReStatic if !cx.verbose => {}
+            region => {
+                if !region_prints_empty(region) {
+                    try!(write!(cx.fmt, " + "));
+                    try!(region.print(cx));
+                }
+            }
         }
 
         Ok(())
     }
 }
 
+impl<'tcx> fmt::Display for ty::TraitTy<'tcx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        PrintCx::with(f, |cx| self.print(cx))
+    }
+}
+
 impl<'tcx> fmt::Debug for ty::TypeParameterDef<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "TypeParameterDef({:?}, {:?}/{})",
@@ -422,10 +762,14 @@ impl fmt::Debug for ty::Region {
     }
 }
 
-impl fmt::Display for ty::Region {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if verbose() {
-            return write!(f, "{:?}", *self);
+impl Print for ty::Region {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        if let Some(number) = REGION_HIGHLIGHT_MODE.with(|c| c.borrow().region_highlighted(*self)) {
+            return write!(cx.fmt, "'{}", number);
+        }
+
+        if cx.verbose {
+            return write!(cx.fmt, "{:?}", *self);
         }
 
         // These printouts are concise.  They do not contain all the information
@@ -434,21 +778,30 @@ impl fmt::Display for ty::Region {
         // `explain_region()` or `note_and_explain_region()`.
         match *self {
             ty::ReEarlyBound(ref data) => {
-                write!(f, "{}", data.name)
+                write!(cx.fmt, "{}", data.name)
             }
             ty::ReLateBound(_, br) |
             ty::ReFree(ty::FreeRegion { bound_region: br, .. }) |
             ty::ReInfer(ReSkolemized(_, br)) => {
-                write!(f, "{}", br)
+                if let Some(number) = REGION_HIGHLIGHT_MODE.with(|c| c.borrow().bound_region_highlighted(br)) {
+                    return write!(cx.fmt, "'{}", number);
+                }
+                write!(cx.fmt, "{}", br)
             }
             ty::ReScope(_) |
             ty::ReInfer(ReVar(_)) => Ok(()),
-            ty::ReStatic => write!(f, "'static"),
-            ty::ReEmpty => write!(f, "'<empty>"),
+            ty::ReStatic => write!(cx.fmt, "'static"),
+            ty::ReEmpty => write!(cx.fmt, "'<empty>"),
         }
     }
 }
 
+impl fmt::Display for ty::Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        PrintCx::with(f, |cx| self.print(cx))
+    }
+}
+
 impl fmt::Debug for ty::FreeRegion {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ReFree({:?}, {:?})",
@@ -488,10 +841,16 @@ impl<'tcx> fmt::Debug for ty::ImplOrTraitItem<'tcx> {
     }
 }
 
+impl<'tcx> Print for ty::FnSig<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        try!(write!(cx.fmt, "fn"));
+        fn_sig(cx, &self.inputs, self.variadic, self.output)
+    }
+}
+
 impl<'tcx> fmt::Display for ty::FnSig<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(write!(f, "fn"));
-        fn_sig(f, &self.inputs, self.variadic, self.output)
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 
@@ -572,153 +931,265 @@ impl fmt::Display for ty::BuiltinBounds {
     }
 }
 
-// The generic impl doesn't work yet because projections are not
-// normalized under HRTB.
-/*impl<T> fmt::Display for ty::Binder<T>
-    where T: fmt::Display + for<'a> ty::Lift<'a>,
-          for<'a> <T as ty::Lift<'a>>::Lifted: fmt::Display + TypeFoldable<'a>
+// This used to require one hand-written impl per `Binder<T>` instantiation
+// because the projections nested inside `T` are not normalized until the
+// higher-ranked binder's regions are resolved, and `in_binder` used to run
+// before that normalization happened. Now that `in_binder` normalizes the
+// value it produces (see above), a single generic impl covers every
+// `Binder<T>` we print.
+impl<T> Print for ty::Binder<T>
+    where T: Print + for<'a> ty::Lift<'a>,
+          for<'a> <T as ty::Lift<'a>>::Lifted: Print + TypeFoldable<'a>
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
-    }
-}*/
-
-impl<'tcx> fmt::Display for ty::Binder<ty::TraitRef<'tcx>> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
-    }
-}
-
-impl<'tcx> fmt::Display for ty::Binder<ty::TraitPredicate<'tcx>> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
-    }
-}
-
-impl<'tcx> fmt::Display for ty::Binder<ty::EquatePredicate<'tcx>> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
-    }
-}
-
-impl<'tcx> fmt::Display for ty::Binder<ty::ProjectionPredicate<'tcx>> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        let lifted = cx.tcx.lift(self);
+        in_binder(cx, self, lifted)
     }
 }
 
-impl<'tcx> fmt::Display for ty::Binder<ty::OutlivesPredicate<Ty<'tcx>, ty::Region>> {
+impl<T> fmt::Display for ty::Binder<T>
+    where T: Print + for<'a> ty::Lift<'a>,
+          for<'a> <T as ty::Lift<'a>>::Lifted: Print + TypeFoldable<'a>
+{
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 
-impl fmt::Display for ty::Binder<ty::OutlivesPredicate<ty::Region, ty::Region>> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ty::tls::with(|tcx| in_binder(f, tcx, self, tcx.lift(self)))
+impl<'tcx> Print for ty::TraitRef<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        parameterized(cx, self.substs, self.def_id, &[],
+                      |tcx| ty::lookup_trait_def(tcx, self.def_id).generics.clone())
     }
 }
 
 impl<'tcx> fmt::Display for ty::TraitRef<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        parameterized(f, self.substs, self.def_id, &[],
-                      |tcx| ty::lookup_trait_def(tcx, self.def_id).generics.clone())
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 
-impl<'tcx> fmt::Display for ty::TypeVariants<'tcx> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl<'tcx> Print for ty::TypeVariants<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
         match *self {
-            TyBool => write!(f, "bool"),
-            TyChar => write!(f, "char"),
-            TyInt(t) => write!(f, "{}", ast_util::int_ty_to_string(t, None)),
-            TyUint(t) => write!(f, "{}", ast_util::uint_ty_to_string(t, None)),
-            TyFloat(t) => write!(f, "{}", ast_util::float_ty_to_string(t)),
-            TyBox(typ) => write!(f, "Box<{}>",  typ),
+            TyBool => write!(cx.fmt, "bool"),
+            TyChar => write!(cx.fmt, "char"),
+            TyNever => write!(cx.fmt, "!"),
+            TyInt(t) => write!(cx.fmt, "{}", ast_util::int_ty_to_string(t, None)),
+            TyUint(t) => write!(cx.fmt, "{}", ast_util::uint_ty_to_string(t, None)),
+            TyFloat(t) => write!(cx.fmt, "{}", ast_util::float_ty_to_string(t)),
+            TyBox(typ) => {
+                try!(write!(cx.fmt, "Box<"));
+                try!(typ.print(cx));
+                write!(cx.fmt, ">")
+            }
             TyRawPtr(ref tm) => {
-                write!(f, "*{} {}", match tm.mutbl {
+                try!(write!(cx.fmt, "*{} ", match tm.mutbl {
                     ast::MutMutable => "mut",
                     ast::MutImmutable => "const",
-                },  tm.ty)
+                }));
+                tm.ty.print(cx)
             }
             TyRef(r, ref tm) => {
-                try!(write!(f, "&"));
-                let s = r.to_string();
-                try!(write!(f, "{}", s));
-                if !s.is_empty() {
-                    try!(write!(f, " "));
+                try!(write!(cx.fmt, "&"));
+                try!(r.print(cx));
+                if !region_prints_empty(r) {
+                    try!(write!(cx.fmt, " "));
                 }
-                write!(f, "{}", tm)
+                write!(cx.fmt, "{}", tm)
             }
             TyTuple(ref tys) => {
-                try!(write!(f, "("));
+                try!(write!(cx.fmt, "("));
                 let mut tys = tys.iter();
                 if let Some(&ty) = tys.next() {
-                    try!(write!(f, "{},", ty));
+                    try!(ty.print(cx));
+                    try!(write!(cx.fmt, ","));
                     if let Some(&ty) = tys.next() {
-                        try!(write!(f, " {}", ty));
+                        try!(write!(cx.fmt, " "));
+                        try!(ty.print(cx));
                         for &ty in tys {
-                            try!(write!(f, ", {}", ty));
+                            try!(write!(cx.fmt, ", "));
+                            try!(ty.print(cx));
                         }
                     }
                 }
-                write!(f, ")")
+                write!(cx.fmt, ")")
             }
             TyBareFn(opt_def_id, ref bare_fn) => {
                 if bare_fn.unsafety == ast::Unsafety::Unsafe {
-                    try!(write!(f, "unsafe "));
+                    try!(write!(cx.fmt, "unsafe "));
                 }
 
                 if bare_fn.abi != abi::Rust {
-                    try!(write!(f, "extern {} ", bare_fn.abi));
+                    try!(write!(cx.fmt, "extern {} ", bare_fn.abi));
                 }
 
-                try!(write!(f, "{}", bare_fn.sig.0));
+                try!(bare_fn.sig.0.print(cx));
 
                 if let Some(def_id) = opt_def_id {
-                    try!(write!(f, " {{{}}}", ty::tls::with(|tcx| {
-                        ty::item_path_str(tcx, def_id)
-                    })));
+                    try!(write!(cx.fmt, " {{{}}}", resolved_path_str(cx, def_id)));
                 }
                 Ok(())
             }
-            TyInfer(infer_ty) => write!(f, "{}", infer_ty),
-            TyError => write!(f, "[type error]"),
-            TyParam(ref param_ty) => write!(f, "{}", param_ty),
+            TyInfer(infer_ty) => write!(cx.fmt, "{}", infer_ty),
+            TyError => write!(cx.fmt, "[type error]"),
+            TyParam(ref param_ty) => write!(cx.fmt, "{}", param_ty),
             TyEnum(did, substs) | TyStruct(did, substs) => {
-                parameterized(f, substs, did, &[],
+                parameterized(cx, substs, did, &[],
                               |tcx| ty::lookup_item_type(tcx, did).generics)
             }
-            TyTrait(ref data) => write!(f, "{}", data),
-            ty::TyProjection(ref data) => write!(f, "{}", data),
-            TyStr => write!(f, "str"),
-            TyClosure(ref did, substs) => ty::tls::with(|tcx| {
-                try!(write!(f, "[closure"));
+            TyTrait(ref data) => data.print(cx),
+            ty::TyProjection(ref data) => write!(cx.fmt, "{}", data),
+            TyStr => write!(cx.fmt, "str"),
+            TyClosure(ref did, substs) => {
+                try!(write!(cx.fmt, "[closure"));
+                let tcx = cx.tcx;
                 let closure_tys = tcx.closure_tys.borrow();
                 try!(closure_tys.get(did).map(|cty| &cty.sig).and_then(|sig| {
                     tcx.lift(&substs).map(|substs| sig.subst(tcx, substs))
                 }).map(|sig| {
-                    fn_sig(f, &sig.0.inputs, false, sig.0.output)
+                    fn_sig(cx, &sig.0.inputs, false, sig.0.output)
                 }).unwrap_or_else(|| {
                     if did.krate == ast::LOCAL_CRATE {
-                        try!(write!(f, " {:?}", tcx.map.span(did.node)));
+                        try!(write!(cx.fmt, " {:?}", tcx.map.span(did.node)));
+                    } else {
+                        // No cached signature and no local span to point
+                        // at (the closure was defined in an upstream
+                        // crate): fall back to naming the defining item
+                        // through `resolved_path_str`, so it follows the
+                        // active path-naming toggles.
+                        try!(write!(cx.fmt, " {}", resolved_path_str(cx, *did)));
                     }
                     Ok(())
                 }));
-                if verbose() {
-                    try!(write!(f, " id={:?}", did));
+                if cx.verbose {
+                    try!(write!(cx.fmt, " id={:?}", did));
+                }
+                write!(cx.fmt, "]")
+            }
+            TyGenerator(ref did, substs) => {
+                try!(write!(cx.fmt, "[generator"));
+                let tcx = cx.tcx;
+                let generator_sigs = tcx.generator_sigs.borrow();
+                try!(generator_sigs.get(did).and_then(|sig| {
+                    tcx.lift(&substs).map(|substs| sig.subst(tcx, substs))
+                }).map(|sig| {
+                    write!(cx.fmt, "({}) -> ({}, {})",
+                           sig.0.resume_ty, sig.0.yield_ty, sig.0.return_ty)
+                }).unwrap_or_else(|| {
+                    if did.krate == ast::LOCAL_CRATE {
+                        try!(write!(cx.fmt, " {:?}", tcx.map.span(did.node)));
+                    }
+                    Ok(())
+                }));
+                if cx.verbose {
+                    try!(write!(cx.fmt, " id={:?}", did));
+                }
+                write!(cx.fmt, "]")
+            }
+            TyArray(ty, ref sz) => {
+                try!(write!(cx.fmt, "["));
+                try!(ty.print(cx));
+                try!(write!(cx.fmt, "; "));
+                try!(fmt_const_len(cx.fmt, sz));
+                write!(cx.fmt, "]")
+            }
+            TySlice(ty) => {
+                try!(write!(cx.fmt, "["));
+                try!(ty.print(cx));
+                write!(cx.fmt, "]")
+            }
+            ty::TyAnon(did, substs) => {
+                // Print the existential bounds registered for this `impl
+                // Trait` type, substituted with the actual substs, e.g.
+                // `impl Iterator<Item = u32>`. The principal trait's
+                // projection bounds are folded into its own angle brackets
+                // via `parameterized`, the same way any other trait ref in
+                // this file prints its associated-type bindings, rather
+                // than rendered as standalone `Projection` predicates --
+                // printing each bound's raw `ty::Predicate` form would give
+                // `<anon>: Iterator + <<anon> as Iterator>::Item == u32`,
+                // exposing the synthetic self type and the predicate's
+                // `==` syntax instead of ordinary trait syntax.
+                let tcx = cx.tcx;
+                let bounds = ty::lookup_predicates(tcx, did).instantiate(tcx, substs);
+
+                let mut principal = None;
+                let mut projection_bounds = Vec::new();
+                let mut other_bounds = Vec::new();
+                for predicate in &bounds.predicates {
+                    match *predicate {
+                        ty::Predicate::Trait(ref data) => {
+                            // Don't print the `Sized` bound that is
+                            // implicitly added to almost every type
+                            // parameter; it's noise.
+                            if Some(data.0.def_id()) == tcx.lang_items.sized_trait()
+                                && !cx.verbose {
+                                continue;
+                            }
+                            if principal.is_none() {
+                                principal = Some(data.0.trait_ref);
+                            } else {
+                                other_bounds.push(data.0.trait_ref.to_string());
+                            }
+                        }
+                        ty::Predicate::Projection(ref data) => {
+                            projection_bounds.push(data.0.clone());
+                        }
+                        ty::Predicate::TypeOutlives(ref data) => {
+                            // Only the region: the predicate's subject is
+                            // this anon type itself.
+                            other_bounds.push((data.0).1.to_string());
+                        }
+                        ty::Predicate::RegionOutlives(ref data) => {
+                            other_bounds.push(data.to_string());
+                        }
+                        ty::Predicate::Equate(_) => {}
+                    }
+                }
+
+                try!(write!(cx.fmt, "impl"));
+                let mut first = true;
+
+                if let Some(trait_ref) = principal {
+                    try!(write!(cx.fmt, " "));
+                    try!(parameterized(cx, trait_ref.substs, trait_ref.def_id,
+                                       &projection_bounds,
+                                       |tcx| ty::lookup_trait_def(tcx, trait_ref.def_id)
+                                                .generics.clone()));
+                    first = false;
                 }
-                write!(f, "]")
-            }),
-            TyArray(ty, sz) => write!(f, "[{}; {}]",  ty, sz),
-            TySlice(ty) => write!(f, "[{}]",  ty)
+
+                for bound in &other_bounds {
+                    try!(write!(cx.fmt, "{}{}", if first { " " } else { " + " }, bound));
+                    first = false;
+                }
+
+                if cx.verbose {
+                    try!(write!(cx.fmt, " id={:?}", did));
+                }
+
+                Ok(())
+            }
         }
     }
 }
 
+impl<'tcx> fmt::Display for ty::TypeVariants<'tcx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        PrintCx::with(f, |cx| self.print(cx))
+    }
+}
+
+impl<'tcx> Print for ty::TyS<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        self.sty.print(cx)
+    }
+}
+
 impl<'tcx> fmt::Display for ty::TyS<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.sty)
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 
@@ -779,17 +1250,35 @@ impl fmt::Debug for ty::ParamTy {
     }
 }
 
+impl<'tcx, T, U> Print for ty::OutlivesPredicate<T, U>
+    where T: Print, U: Print
+{
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        try!(self.0.print(cx));
+        try!(write!(cx.fmt, " : "));
+        self.1.print(cx)
+    }
+}
+
 impl<'tcx, T, U> fmt::Display for ty::OutlivesPredicate<T,U>
-    where T: fmt::Display, U: fmt::Display
+    where T: Print, U: Print
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} : {}", self.0, self.1)
+        PrintCx::with(f, |cx| self.print(cx))
+    }
+}
+
+impl<'tcx> Print for ty::EquatePredicate<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        try!(self.0.print(cx));
+        try!(write!(cx.fmt, " == "));
+        self.1.print(cx)
     }
 }
 
 impl<'tcx> fmt::Display for ty::EquatePredicate<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} == {}", self.0, self.1)
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 
@@ -800,11 +1289,17 @@ impl<'tcx> fmt::Debug for ty::TraitPredicate<'tcx> {
     }
 }
 
+impl<'tcx> Print for ty::TraitPredicate<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        try!(self.trait_ref.self_ty().print(cx));
+        try!(write!(cx.fmt, " : "));
+        self.trait_ref.print(cx)
+    }
+}
+
 impl<'tcx> fmt::Display for ty::TraitPredicate<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} : {}",
-               self.trait_ref.self_ty(),
-               self.trait_ref)
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 
@@ -816,19 +1311,29 @@ impl<'tcx> fmt::Debug for ty::ProjectionPredicate<'tcx> {
     }
 }
 
+impl<'tcx> Print for ty::ProjectionPredicate<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        try!(self.projection_ty.print(cx));
+        try!(write!(cx.fmt, " == "));
+        self.ty.print(cx)
+    }
+}
+
 impl<'tcx> fmt::Display for ty::ProjectionPredicate<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} == {}",
-               self.projection_ty,
-               self.ty)
+        PrintCx::with(f, |cx| self.print(cx))
+    }
+}
+
+impl<'tcx> Print for ty::ProjectionTy<'tcx> {
+    fn print(&self, cx: &mut PrintCx) -> fmt::Result {
+        write!(cx.fmt, "{:?}::{}", self.trait_ref, self.item_name)
     }
 }
 
 impl<'tcx> fmt::Display for ty::ProjectionTy<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}::{}",
-               self.trait_ref,
-               self.item_name)
+        PrintCx::with(f, |cx| self.print(cx))
     }
 }
 